@@ -2,12 +2,79 @@ extern crate proc_macro;
 
 use proc_macro2::{TokenStream, Span};
 use quote::{quote, format_ident};
-use syn::{parse_macro_input, Ident, DeriveInput, Type};
+use syn::{parse_macro_input, Ident, DeriveInput, Type, AttributeArgs, NestedMeta, Meta, Lit};
+
+/// The encoding of arguments and results passed across the WASM boundary.
+#[derive(Clone, Copy)]
+enum WireFormat {
+    /// Human-readable JSON5 strings, requires the `json5` crate.
+    Json5,
+    /// Compact byte buffers, requires the `bincode` crate.
+    Binary,
+}
+
+impl WireFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json5" => Some(WireFormat::Json5),
+            "binary" => Some(WireFormat::Binary),
+            _ => None,
+        }
+    }
+
+    fn to_serialized_type_tokens(self) -> TokenStream {
+        match self {
+            WireFormat::Json5 => quote! { String },
+            WireFormat::Binary => quote! { Vec<u8> },
+        }
+    }
+
+    fn to_serialize_tokens(self, value: TokenStream) -> TokenStream {
+        match self {
+            WireFormat::Json5 => quote! { ::json5::to_string(&#value) },
+            WireFormat::Binary => quote! { ::bincode::serialize(&#value) },
+        }
+    }
+
+    fn to_deserialize_tokens(self, serialized: TokenStream, ty: TokenStream) -> TokenStream {
+        match self {
+            WireFormat::Json5 => quote! { ::json5::from_str::<#ty>(&#serialized) },
+            WireFormat::Binary => quote! { ::bincode::deserialize::<#ty>(&#serialized) },
+        }
+    }
+
+    /// Serializes `result`, a `Result<_, mlib::MappCallError>`, without
+    /// panicking. Should that fail, a `MappCallError::ResultSerialization` is
+    /// serialized instead, or as a last resort, an empty value, which the host
+    /// fails to deserialize.
+    fn to_serialize_envelope_tokens(self, method: &str) -> TokenStream {
+        let serialize_result = self.to_serialize_tokens(quote! { result });
+
+        quote! {
+            #serialize_result
+                .or_else(|e| {
+                    let result: Result<(), mlib::MappCallError> = Err(mlib::MappCallError::ResultSerialization {
+                        method: #method.to_string(),
+                        message: e.to_string(),
+                    });
+
+                    #serialize_result
+                })
+                .unwrap_or_default()
+        }
+    }
+}
 
 struct MappFunctionHeader {
     ident: Ident,
     arguments: Vec<(Ident, Type)>,
     return_type: Type,
+    /// The default implementation of the method in the `Mapp` trait, if any.
+    default_body: Option<TokenStream>,
+    /// Whether `default_body` is also the default implementation in the
+    /// `MappInterface` trait. Not the case for bodies relying on methods only
+    /// available on `Mapp`, such as `command_client`.
+    default_in_interface: bool,
 }
 
 impl MappFunctionHeader {
@@ -19,45 +86,63 @@ impl MappFunctionHeader {
             }).collect();
         let return_ty = &self.return_type;
 
-        quote! {
-            fn #ident(&mut self#(, #args)*) -> #return_ty;
+        match &self.default_body {
+            Some(default_body) => quote! {
+                fn #ident(&mut self#(, #args)*) -> #return_ty #default_body
+            },
+            None => quote! {
+                fn #ident(&mut self#(, #args)*) -> #return_ty;
+            },
         }
     }
 
-    fn to_client_exported_fn_tokens(&self, implementor_type: &Ident) -> TokenStream {
+    fn to_client_exported_fn_tokens(&self, implementor_type: &Ident, format: WireFormat) -> TokenStream {
         let ident = &self.ident;
         let arg_idents: Vec<_> = self.arguments[..].iter()
             .map(|(arg_ident, _)| arg_ident).collect();
         let arg_tys: Vec<_> = self.arguments[..].iter()
             .map(|(_, arg_ty)| arg_ty).collect();
         let return_ty = &self.return_type;
+        let serialized_ty = format.to_serialized_type_tokens();
+        let deserialize_args = format.to_deserialize_tokens(quote! { args }, quote! { (#(#arg_tys, )*) });
+        let serialize_envelope = format.to_serialize_envelope_tokens(&ident.to_string());
 
         quote! {
             #[wasm_bindgen]
-            pub fn #ident(args: String) -> String {
-                let (#(#arg_idents, )*) = ::json5::from_str::<(#(#arg_tys, )*)>(&args)
-                    .unwrap_or_else(|e| panic!("Could not deserialize host-provided arguments of the method '{}': {:?}", stringify!(#ident), e));
-                let mut ctx = __internal_mlib::MAPP_GLOBAL.write()
-                    .unwrap_or_else(|e| panic!("Global state of the Mapp became poisoned: {}", e));
-                let ctx = ctx.as_mut()
-                    .unwrap_or_else(|| panic!("Method '{}' called without initialization of the Mapp.", stringify!(#ident)));
-                let result: #return_ty = <#implementor_type as Mapp>::#ident(ctx, #(#arg_idents, )*);
-
-                ::json5::to_string(&result)
-                    .unwrap_or_else(|e| panic!("Could not serialize the result of calling the method '{}': {:?}", stringify!(#ident), e))
+            pub fn #ident(args: #serialized_ty) -> #serialized_ty {
+                fn call(args: #serialized_ty) -> Result<#return_ty, mlib::MappCallError> {
+                    let (#(#arg_idents, )*) = #deserialize_args
+                        .map_err(|e| mlib::MappCallError::ArgumentDeserialization {
+                            method: stringify!(#ident).to_string(),
+                            message: e.to_string(),
+                        })?;
+                    let mut ctx = __internal_mlib::MAPP_GLOBAL.write()
+                        .map_err(|_| mlib::MappCallError::StatePoisoned)?;
+                    let ctx = ctx.as_mut()
+                        .ok_or_else(|| mlib::MappCallError::Uninitialized {
+                            method: stringify!(#ident).to_string(),
+                        })?;
+
+                    Ok(<#implementor_type as Mapp>::#ident(ctx, #(#arg_idents, )*))
+                }
+
+                let result = call(args);
+
+                #serialize_envelope
             }
         }
     }
 
-    fn to_host_header_tokens(&self) -> TokenStream {
+    fn to_host_header_tokens(&self, format: WireFormat) -> TokenStream {
         let ident = &self.ident;
+        let serialized_ty = format.to_serialized_type_tokens();
 
         quote! {
-            fn #ident(&mut self, serialized_args: String) -> String;
+            fn #ident(&mut self, serialized_args: #serialized_ty) -> #serialized_ty;
         }
     }
 
-    fn to_host_imported_fn_tokens(&self) -> TokenStream {
+    fn to_host_imported_fn_tokens(&self, format: WireFormat) -> TokenStream {
         let ident = &self.ident;
         let args: Vec<_> = self.arguments[..].iter()
             .map(|(arg_ident, arg_ty)| quote! {
@@ -66,15 +151,50 @@ impl MappFunctionHeader {
         let arg_idents: Vec<_> = self.arguments[..].iter()
             .map(|(arg_ident, _)| arg_ident).collect();
         let return_ty = &self.return_type;
+        let try_ident = format_ident!("try_{}", ident);
+        let serialize_args = format.to_serialize_tokens(quote! { (#(#arg_idents, )*) });
+        let deserialize_result = format.to_deserialize_tokens(
+            quote! { serialized_result },
+            quote! { Result<#return_ty, mlib::MappCallError> },
+        );
 
         quote! {
-            fn #ident(&mut self, #(#args, )*) -> #return_ty {
-                let serialized_args = ::json5::to_string(&(#(#arg_idents, )*))
-                    .unwrap_or_else(|e| panic!("Could not serialize client-provided arguments of the method '{}': {:?}", stringify!(#ident), e));
-                let serialized_result = self.exports.#ident(serialized_args);
+            fn #try_ident(&mut self, #(#args, )*) -> Result<#return_ty, mlib::MappError> {
+                let serialized_args = #serialize_args
+                    .map_err(|e| mlib::MappError::Serialization {
+                        method: stringify!(#ident).to_string(),
+                        message: e.to_string(),
+                    })?;
+                let exports = &mut self.exports;
+                let serialized_result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                    exports.#ident(serialized_args)
+                })).map_err(|payload| mlib::MappError::from_panic(stringify!(#ident), payload))?;
+                let result = #deserialize_result
+                    .map_err(|e| mlib::MappError::Serialization {
+                        method: stringify!(#ident).to_string(),
+                        message: e.to_string(),
+                    })?;
+
+                result.map_err(mlib::MappError::from)
+            }
+        }
+    }
 
-                ::json5::from_str(&serialized_result)
-                    .unwrap_or_else(|e| panic!("Could not deserialize the result of calling the method '{}': {:?}", stringify!(#ident), e))
+    fn to_host_delegate_fn_tokens(&self) -> TokenStream {
+        let ident = &self.ident;
+        let try_ident = format_ident!("try_{}", ident);
+        let args: Vec<_> = self.arguments[..].iter()
+            .map(|(arg_ident, arg_ty)| quote! {
+                #arg_ident: #arg_ty
+            }).collect();
+        let arg_idents: Vec<_> = self.arguments[..].iter()
+            .map(|(arg_ident, _)| arg_ident).collect();
+        let return_ty = &self.return_type;
+
+        quote! {
+            fn #ident(&mut self, #(#args, )*) -> #return_ty {
+                mlib::TryMappInterface::#try_ident(self, #(#arg_idents, )*)
+                    .unwrap_or_else(|e| panic!("Calling the method '{}' of the Mapp failed: {}", stringify!(#ident), e))
             }
         }
     }
@@ -104,15 +224,33 @@ impl MappFunctionHeader {
             }).collect();
         let return_ty = &self.return_type;
 
+        match &self.default_body {
+            Some(default_body) if self.default_in_interface => quote! {
+                fn #ident(&mut self, #(#args, )*) -> #return_ty #default_body
+            },
+            _ => quote! {
+                fn #ident(&mut self, #(#args, )*) -> #return_ty;
+            },
+        }
+    }
+
+    fn to_host_imported_try_fn_header_tokens(&self) -> TokenStream {
+        let try_ident = format_ident!("try_{}", &self.ident);
+        let args: Vec<_> = self.arguments[..].iter()
+            .map(|(arg_ident, arg_ty)| quote! {
+                #arg_ident: #arg_ty
+            }).collect();
+        let return_ty = &self.return_type;
+
         quote! {
-            fn #ident(&mut self, #(#args, )*) -> #return_ty;
+            fn #try_ident(&mut self, #(#args, )*) -> Result<#return_ty, mlib::MappError>;
         }
     }
 }
 
 macro_rules! mapp_function_header {
     {
-        fn $ident:ident (&mut self$(, $arg_ident:ident: $arg_ty:ty)* $(,)?) -> $return_ty:ty;
+        fn $ident:ident (&mut self$(, $arg_ident:ident: $arg_ty:ty)* $(,)?) -> $return_ty:ty $($default_body:block)?;
     } => {{
         MappFunctionHeader {
             ident: Ident::new(stringify!($ident), Span::call_site()),
@@ -131,14 +269,16 @@ macro_rules! mapp_function_header {
                 let ts = quote! { $return_ty }.into();
                 parse_macro_input!(ts as Type)
             },
+            default_body: None$(.or(Some(quote! { $default_body })))?,
+            default_in_interface: true,
         }
     }};
 
     {
-        fn $ident:ident (&mut self$(, $arg_ident:ident: $arg_ty:ty)* $(,)?);
+        fn $ident:ident (&mut self$(, $arg_ident:ident: $arg_ty:ty)* $(,)?) $($default_body:block)?;
     } => {{
         mapp_function_header! {
-            fn $ident (&mut self$(, $arg_ident: $arg_ty)*) -> ();
+            fn $ident (&mut self$(, $arg_ident: $arg_ty)*) -> () $($default_body)?;
         }
     }};
 }
@@ -146,15 +286,20 @@ macro_rules! mapp_function_header {
 macro_rules! mapp_function_headers {
     {
         $(
-            fn $ident:ident (&mut self$(, $arg_ident:ident: $arg_ty:ty)* $(,)?)$( -> $return_ty:ty)?
+            $(#[$marker:ident])?
+            fn $ident:ident (&mut self$(, $arg_ident:ident: $arg_ty:ty)* $(,)?)$( -> $return_ty:ty)? $($default_body:block)?
         );* $(;)?
     } => {{
         [
-            $(
-                mapp_function_header! {
-                    fn $ident (&mut self$(, $arg_ident: $arg_ty)*)$( -> $return_ty)?;
-                }
-            ),*
+            $({
+                #[allow(unused_mut)]
+                let mut header = mapp_function_header! {
+                    fn $ident (&mut self$(, $arg_ident: $arg_ty)*)$( -> $return_ty)? $($default_body)?;
+                };
+
+                $(header.default_in_interface = stringify!($marker) != "mapp_only";)?
+                header
+            }),*
         ]
     }};
 }
@@ -162,7 +307,8 @@ macro_rules! mapp_function_headers {
 /// Generates function exports for a WASM module
 fn generate_client_interface(
     input: proc_macro::TokenStream,
-    mapp_function_headers: &[MappFunctionHeader]
+    mapp_function_headers: &[MappFunctionHeader],
+    format: WireFormat,
 ) -> proc_macro::TokenStream {
     let input_cloned = input.clone();
     let parsed_input = parse_macro_input!(input_cloned as DeriveInput);
@@ -171,11 +317,14 @@ fn generate_client_interface(
         .map(|f| f.to_client_header_tokens())
         .collect();
     let mapp_exported_functions: Vec<_> = mapp_function_headers.iter()
-        .map(|f| f.to_client_exported_fn_tokens(&implementor_type))
+        .map(|f| f.to_client_exported_fn_tokens(&implementor_type, format))
         .collect();
     let mapp_delegate_functions: Vec<_> = mapp_function_headers.iter()
         .map(|f| f.to_fn_delegate_from_native_tokens(&implementor_type))
         .collect();
+    let serialized_ty = format.to_serialized_type_tokens();
+    let deserialize_host_info = format.to_deserialize_tokens(quote! { host_info }, quote! { mlib::HostInfo });
+    let serialize_initialize_envelope = format.to_serialize_envelope_tokens("initialize");
     let expanded = quote! {
         mod __internal_mlib {
             use ::std::marker::PhantomData;
@@ -186,6 +335,15 @@ fn generate_client_interface(
             // TYPES
             pub trait Mapp {
                 fn new() -> Self;
+                /// Called after `new`, once the protocol version has been
+                /// agreed upon.
+                fn init(&mut self, _host_info: &mlib::HostInfo) {}
+                /// The client to send commands and receive responses through,
+                /// unless `send_command` and `receive_command_response` are
+                /// implemented manually.
+                fn command_client(&self) -> Option<&mlib::CommandClient> {
+                    None
+                }
                 #(#mapp_function_headers_ts)*
             }
 
@@ -202,6 +360,10 @@ fn generate_client_interface(
             }
 
             impl mlib::MappInterface for #implementor_type {
+                fn init(&mut self, host_info: &mlib::HostInfo) {
+                    <#implementor_type as Mapp>::init(self, host_info)
+                }
+
                 #(#mapp_delegate_functions)*
             }
         }
@@ -209,17 +371,62 @@ fn generate_client_interface(
         pub use __internal_mlib::Mapp;
 
         // EXPORTED FUNCTIONS
+        /// Checks whether the Mapp implements a protocol version supported by
+        /// the host and if so, initializes it. Returns the protocol version of
+        /// the Mapp or `MappCallError::ProtocolMismatch`.
         #[wasm_bindgen]
-        pub fn initialize() {
-            *(__internal_mlib::MAPP_GLOBAL)
-                .write()
-                .unwrap_or_else(|e| panic!("Global state of the Mapp became poisoned: {}", e))
-                = Some(<#implementor_type as Mapp>::new());
+        pub fn initialize(host_info: #serialized_ty) -> #serialized_ty {
+            fn call(host_info: #serialized_ty) -> Result<mlib::ProtocolVersion, mlib::MappCallError> {
+                let host_info = #deserialize_host_info
+                    .map_err(|e| mlib::MappCallError::ArgumentDeserialization {
+                        method: "initialize".to_string(),
+                        message: e.to_string(),
+                    })?;
+
+                if !host_info.protocol_versions.supports(mlib::PROTOCOL_VERSION) {
+                    return Err(mlib::MappCallError::ProtocolMismatch(mlib::ProtocolMismatch {
+                        mapp_version: mlib::PROTOCOL_VERSION,
+                        host_versions: host_info.protocol_versions,
+                    }));
+                }
+
+                let mut mapp = <#implementor_type as Mapp>::new();
+
+                <#implementor_type as Mapp>::init(&mut mapp, &host_info);
+
+                *(__internal_mlib::MAPP_GLOBAL)
+                    .write()
+                    .map_err(|_| mlib::MappCallError::StatePoisoned)?
+                    = Some(mapp);
+
+                Ok(mlib::PROTOCOL_VERSION)
+            }
+
+            let result = call(host_info);
+
+            #serialize_initialize_envelope
         }
 
+        // Linear memory offsets are 32-bit, so `u32` is used across the WASM
+        // boundary, rather than `usize`, which the host sees as 64-bit.
+
+        /// Returns the address of a shared buffer in the linear memory, or 0,
+        /// if the buffer has been released.
         #[wasm_bindgen]
-        pub fn api_version() -> String {
-            env!("CARGO_PKG_VERSION").to_string()
+        pub fn buffer_pointer(buffer: u32) -> u32 {
+            mlib::Buffer(buffer as usize).location().map(|(pointer, _)| pointer as u32).unwrap_or(0)
+        }
+
+        /// Returns the length of a shared buffer, or 0, if the buffer has been
+        /// released.
+        #[wasm_bindgen]
+        pub fn buffer_len(buffer: u32) -> u32 {
+            mlib::Buffer(buffer as usize).location().map(|(_, len)| len as u32).unwrap_or(0)
+        }
+
+        #[wasm_bindgen]
+        pub fn buffer_release(buffer: u32) {
+            mlib::Buffer(buffer as usize).release();
         }
 
         #(#mapp_exported_functions)*
@@ -235,46 +442,118 @@ fn generate_client_interface(
 /// Generates function imports to communicate with a WASM Mapp module
 fn generate_host_interface(
     input: proc_macro::TokenStream,
-    mapp_function_headers: &[MappFunctionHeader]
+    mapp_function_headers: &[MappFunctionHeader],
+    format: WireFormat,
 ) -> proc_macro::TokenStream {
     let input_cloned = input.clone();
     let parsed_input = parse_macro_input!(input_cloned as DeriveInput);
     let implementor_type = parsed_input.ident;
     let mapp_function_headers_ts: Vec<_> = mapp_function_headers.iter()
-        .map(|f| f.to_host_header_tokens())
-        .collect();
-    let mapp_imported_function_headers: Vec<_> = mapp_function_headers.iter()
-        .map(|f| f.to_host_imported_fn_header_tokens())
+        .map(|f| f.to_host_header_tokens(format))
         .collect();
     let mapp_imported_functions: Vec<_> = mapp_function_headers.iter()
-        .map(|f| f.to_host_imported_fn_tokens())
+        .map(|f| f.to_host_imported_fn_tokens(format))
+        .collect();
+    let mapp_delegate_functions: Vec<_> = mapp_function_headers.iter()
+        .map(|f| f.to_host_delegate_fn_tokens())
         .collect();
     let mapp_exports_ident = format_ident!("{}Exports", &implementor_type);
+    let serialized_ty = format.to_serialized_type_tokens();
+    let serialize_host_info = format.to_serialize_tokens(quote! { host_info });
+    let deserialize_initialize_result = format.to_deserialize_tokens(
+        quote! { serialized_result },
+        quote! { Result<mlib::ProtocolVersion, mlib::MappCallError> },
+    );
     let expanded = quote! {
         /// A wasmtime_rust-generated struct with bindings to a WASM container
         #[wasmtime_rust::wasmtime]
         pub trait #mapp_exports_ident {
-            fn initialize(&mut self);
-            fn api_version(&mut self) -> String;
+            fn initialize(&mut self, host_info: #serialized_ty) -> #serialized_ty;
+            fn buffer_pointer(&mut self, buffer: u32) -> u32;
+            fn buffer_len(&mut self, buffer: u32) -> u32;
+            fn buffer_release(&mut self, buffer: u32);
             #(#mapp_function_headers_ts)*
         }
 
         pub struct #implementor_type {
             exports: #mapp_exports_ident,
+            protocol_version: mlib::ProtocolVersion,
         }
 
         impl #implementor_type {
-            /// Loads and initializes the Mapp
-            pub fn initialize(exports: #mapp_exports_ident) -> Self {
-                let mut mapp = #implementor_type { exports };
-                mapp.exports.initialize();
-                mapp
+            /// Loads and initializes the Mapp. Fails with
+            /// `MappError::ProtocolMismatch`, if the Mapp implements a protocol
+            /// version not supported by the host, whether the Mapp detected
+            /// the mismatch itself or not.
+            pub fn initialize(mut exports: #mapp_exports_ident, host_info: mlib::HostInfo) -> Result<Self, mlib::MappError> {
+                let serialized_host_info = #serialize_host_info
+                    .map_err(|e| mlib::MappError::Serialization {
+                        method: "initialize".to_string(),
+                        message: e.to_string(),
+                    })?;
+                let serialized_result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                    exports.initialize(serialized_host_info)
+                })).map_err(|payload| mlib::MappError::from_panic("initialize", payload))?;
+                let protocol_version = #deserialize_initialize_result
+                    .map_err(|e| mlib::MappError::Serialization {
+                        method: "initialize".to_string(),
+                        message: e.to_string(),
+                    })??;
+
+                if !host_info.protocol_versions.supports(protocol_version) {
+                    return Err(mlib::MappError::ProtocolMismatch(mlib::ProtocolMismatch {
+                        mapp_version: protocol_version,
+                        host_versions: host_info.protocol_versions,
+                    }));
+                }
+
+                Ok(#implementor_type { exports, protocol_version })
+            }
+
+            /// The protocol version implemented by the Mapp.
+            pub fn protocol_version(&self) -> mlib::ProtocolVersion {
+                self.protocol_version
+            }
+
+            /// Returns the address and the length of a shared buffer in the
+            /// linear memory of the Mapp, or `None`, if the buffer has been
+            /// released.
+            pub fn buffer_location(&mut self, buffer: mlib::Buffer) -> Option<(u32, u32)> {
+                let pointer = self.exports.buffer_pointer(buffer.0 as u32);
+
+                if pointer == 0 {
+                    None
+                } else {
+                    Some((pointer, self.exports.buffer_len(buffer.0 as u32)))
+                }
+            }
+
+            /// Returns the contents of a shared buffer within `memory`, the
+            /// linear memory exported by the instance of the Mapp as `memory`,
+            /// or `None`, if the buffer has been released or lies outside of
+            /// `memory`. The memory is not accessible through the generated
+            /// bindings, so it is passed in by the host, which instantiated
+            /// the module.
+            pub fn buffer_bytes<'m>(&mut self, memory: &'m [u8], buffer: mlib::Buffer) -> Option<&'m [u8]> {
+                let (pointer, len) = self.buffer_location(buffer)?;
+                let start = pointer as usize;
+
+                memory.get(start..start.checked_add(len as usize)?)
+            }
+
+            /// Releases a shared buffer, once its contents have been read.
+            pub fn buffer_release(&mut self, buffer: mlib::Buffer) {
+                self.exports.buffer_release(buffer.0 as u32);
             }
         }
 
-        impl mlib::MappInterface for #implementor_type {
+        impl mlib::TryMappInterface for #implementor_type {
             #(#mapp_imported_functions)*
         }
+
+        impl mlib::MappInterface for #implementor_type {
+            #(#mapp_delegate_functions)*
+        }
     };
 
     // Hand the output tokens back to the compiler.
@@ -295,15 +574,27 @@ fn generate_typed_interface(
     let mapp_imported_function_headers: Vec<_> = mapp_function_headers.iter()
         .map(|f| f.to_host_imported_fn_header_tokens())
         .collect();
+    let mapp_imported_try_function_headers: Vec<_> = mapp_function_headers.iter()
+        .map(|f| f.to_host_imported_try_fn_header_tokens())
+        .collect();
+    let try_implementor_type = format_ident!("Try{}", &implementor_type);
     let expanded = quote! {
         /// A trait implemented by Mapps
         pub trait #implementor_type {
-            fn api_version(&mut self) -> String {
-                env!("CARGO_PKG_VERSION").to_string()
-            }
+            /// Called by hosts running the Mapp in-process, such as
+            /// `NativeHost`, once the protocol version has been agreed upon.
+            /// WASM Mapps are initialized by their `initialize` export
+            /// instead.
+            fn init(&mut self, _host_info: &mlib::HostInfo) {}
 
             #(#mapp_imported_function_headers)*
         }
+
+        /// A fallible version of the trait implemented by Mapps, which
+        /// reports failures instead of panicking
+        pub trait #try_implementor_type {
+            #(#mapp_imported_try_function_headers)*
+        }
     };
 
     // Hand the output tokens back to the compiler.
@@ -316,19 +607,69 @@ fn generate_typed_interface(
 #[proc_macro_attribute]
 pub fn mapp(args: proc_macro::TokenStream, input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     // Functions, which require serialization, implemented by the Metaview App.
-    // Ensure absolute paths to types are used.
+    // Ensure absolute paths to types are used. Functions with a body are
+    // implemented by default in the `Mapp` and `MappInterface` traits, unless
+    // marked `#[mapp_only]`, because they rely on methods only available on
+    // `Mapp`.
     let mapp_function_headers = mapp_function_headers! {
         // fn test(&mut self, arg: String) -> Vec<String>;
         fn update(&mut self, elapsed: std::time::Duration);
-        fn send_command(&mut self) -> Option<mlib::Command>;
-        fn receive_command_response(&mut self, response: mlib::CommandResponse);
+        #[mapp_only]
+        fn send_command(&mut self) -> Option<mlib::Command> {
+            self.command_client().and_then(|client| client.next_command())
+        };
+        #[mapp_only]
+        fn receive_command_response(&mut self, response: mlib::CommandResponse) {
+            if let Some(client) = self.command_client() {
+                client.receive_response(response);
+            }
+        };
+        // Allow the host to exchange all commands and responses of a frame in
+        // a single call.
+        fn send_commands(&mut self) -> Vec<mlib::Command> {
+            ::std::iter::from_fn(|| self.send_command()).collect()
+        };
+        fn receive_command_responses(&mut self, responses: Vec<mlib::CommandResponse>) {
+            for response in responses {
+                self.receive_command_response(response);
+            }
+        };
         fn flush_io(&mut self) -> mlib::IO;
-        fn receive_event(&mut self, event: mlib::Event);
+        fn receive_event(&mut self, event: mlib::EventEnvelope);
     };
 
-    match args.to_string().as_str() {
-        "host" => generate_host_interface(input, &mapp_function_headers[..]),
-        "interface" => generate_typed_interface(input, &mapp_function_headers[..]),
-        _ => generate_client_interface(input, &mapp_function_headers[..]),
+    // Arguments are of the form `#[mapp(host, format = "binary")]`.
+    let args = parse_macro_input!(args as AttributeArgs);
+    let mut target = None;
+    let mut format = WireFormat::Json5;
+
+    for arg in &args {
+        match arg {
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("host") || path.is_ident("interface") => {
+                target = path.get_ident().map(Ident::to_string);
+            },
+            NestedMeta::Meta(Meta::NameValue(name_value)) if name_value.path.is_ident("format") => {
+                let parsed_format = match &name_value.lit {
+                    Lit::Str(lit) => WireFormat::from_name(&lit.value()),
+                    _ => None,
+                };
+
+                match parsed_format {
+                    Some(parsed_format) => format = parsed_format,
+                    None => return syn::Error::new_spanned(&name_value.lit, "Expected `\"json5\"` or `\"binary\"`.")
+                        .to_compile_error()
+                        .into(),
+                }
+            },
+            _ => return syn::Error::new_spanned(arg, "Unknown argument, expected `host`, `interface` or `format = \"...\"`.")
+                .to_compile_error()
+                .into(),
+        }
+    }
+
+    match target.as_deref() {
+        Some("host") => generate_host_interface(input, &mapp_function_headers[..], format),
+        Some("interface") => generate_typed_interface(input, &mapp_function_headers[..]),
+        _ => generate_client_interface(input, &mapp_function_headers[..], format),
     }
 }
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use serde::{Serialize, Deserialize};
+
+/// A handle to bytes placed in the linear memory of the Mapp, which the host
+/// can read directly, without them being copied into a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Buffer(pub usize);
+
+#[derive(Default)]
+struct SharedBuffers {
+    next_buffer: usize,
+    buffers: HashMap<Buffer, Vec<u8>>,
+}
+
+lazy_static! {
+    static ref SHARED_BUFFERS: Mutex<SharedBuffers> = Mutex::new(Default::default());
+}
+
+fn with_shared_buffers<R>(f: impl FnOnce(&mut SharedBuffers) -> R) -> R {
+    let mut shared_buffers = SHARED_BUFFERS.lock()
+        .unwrap_or_else(|e| panic!("Shared buffers of the Mapp became poisoned: {}", e));
+
+    f(&mut shared_buffers)
+}
+
+impl Buffer {
+    /// Shares the bytes with the host until the buffer is released.
+    pub fn share(bytes: Vec<u8>) -> Self {
+        with_shared_buffers(|shared_buffers| {
+            let buffer = Buffer(shared_buffers.next_buffer);
+
+            shared_buffers.next_buffer += 1;
+            shared_buffers.buffers.insert(buffer, bytes);
+
+            buffer
+        })
+    }
+
+    /// Returns the address and the length of the buffer in the linear memory,
+    /// or `None`, if the buffer has been released.
+    pub fn location(self) -> Option<(usize, usize)> {
+        with_shared_buffers(|shared_buffers| {
+            shared_buffers.buffers.get(&self)
+                .map(|bytes| (bytes.as_ptr() as usize, bytes.len()))
+        })
+    }
+
+    /// Calls `f` with the contents of the buffer, unless it has been released.
+    pub fn with_bytes<R>(self, f: impl FnOnce(&[u8]) -> R) -> Option<R> {
+        with_shared_buffers(|shared_buffers| {
+            shared_buffers.buffers.get(&self)
+                .map(|bytes| f(&bytes[..]))
+        })
+    }
+
+    /// Stops sharing the buffer and returns its contents.
+    pub fn release(self) -> Option<Vec<u8>> {
+        with_shared_buffers(|shared_buffers| {
+            shared_buffers.buffers.remove(&self)
+        })
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use crate::*;
+
+type Task = Pin<Box<dyn Future<Output=()> + Send>>;
+
+#[derive(Default)]
+struct PendingCommand {
+    result: Option<Result<CommandResponseKind, CommandError>>,
+    waker: Option<Waker>,
+}
+
+#[derive(Default)]
+struct SharedState {
+    next_command_id: usize,
+    outgoing_commands: VecDeque<Command>,
+    pending_commands: HashMap<usize, PendingCommand>,
+}
+
+fn noop_raw_waker() -> RawWaker {
+    fn clone(_: *const ()) -> RawWaker {
+        noop_raw_waker()
+    }
+
+    fn noop(_: *const ()) {}
+
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    RawWaker::new(std::ptr::null(), &VTABLE)
+}
+
+/// The response is of a different command than the one sent. Reported by the
+/// Mapp when converting the response, rather than by the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnexpectedResponse {
+    pub expected: &'static str,
+    pub received: &'static str,
+}
+
+impl fmt::Display for UnexpectedResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Expected a response to `{}`, received a response to `{}`", self.expected, self.received)
+    }
+}
+
+impl Error for UnexpectedResponse {}
+
+/// The reason a typed request sent by a `CommandClient` failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestError {
+    /// The host failed to execute the command.
+    Command(CommandError),
+    UnexpectedResponse(UnexpectedResponse),
+}
+
+impl From<CommandError> for RequestError {
+    fn from(error: CommandError) -> Self {
+        RequestError::Command(error)
+    }
+}
+
+impl From<UnexpectedResponse> for RequestError {
+    fn from(error: UnexpectedResponse) -> Self {
+        RequestError::UnexpectedResponse(error)
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RequestError::Command(error) => error.fmt(f),
+            RequestError::UnexpectedResponse(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for RequestError {}
+
+/// Allocates command ids, queues commands to be sent to the host and resolves
+/// `CommandFuture`s as the matching responses arrive. Clones share the same
+/// state, so they can be moved into spawned tasks.
+///
+/// To wire the client to the host, return it from `Mapp::command_client`, or
+/// forward `send_command` to `next_command` and `receive_command_response` to
+/// `receive_response`.
+#[derive(Clone, Default)]
+pub struct CommandClient {
+    state: Arc<Mutex<SharedState>>,
+    tasks: Arc<Mutex<Vec<Task>>>,
+}
+
+impl CommandClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, SharedState> {
+        self.state.lock()
+            .unwrap_or_else(|e| panic!("State of the command client became poisoned: {}", e))
+    }
+
+    fn lock_tasks(&self) -> MutexGuard<'_, Vec<Task>> {
+        self.tasks.lock()
+            .unwrap_or_else(|e| panic!("Tasks of the command client became poisoned: {}", e))
+    }
+
+    /// Queues the command and returns a future resolving to its result.
+    pub fn send(&self, kind: CommandKind) -> CommandFuture {
+        let mut state = self.lock_state();
+        let command_id = state.next_command_id;
+
+        state.next_command_id += 1;
+        state.outgoing_commands.push_back(Command { id: command_id, kind });
+        state.pending_commands.insert(command_id, PendingCommand::default());
+
+        CommandFuture {
+            command_id,
+            state: self.state.clone(),
+        }
+    }
+
+    /// Queues the typed command and returns a future resolving to its typed
+    /// response. Methods named after each command, such as `entity_create`,
+    /// are shorthands for this method.
+    pub fn request<R: CommandRequest>(&self, request: R) -> impl Future<Output=Result<R::Response, RequestError>> {
+        let response = self.send(request.into());
+
+        async move {
+            Ok(R::Response::try_from(response.await?)?)
+        }
+    }
+
+    /// Returns the next queued command to be sent to the host.
+    pub fn next_command(&self) -> Option<Command> {
+        self.lock_state().outgoing_commands.pop_front()
+    }
+
+    /// Returns all queued commands to be sent to the host.
+    pub fn take_commands(&self) -> Vec<Command> {
+        self.lock_state().outgoing_commands.drain(..).collect()
+    }
+
+    /// Resolves the future of the matching command and polls spawned tasks.
+    /// Responses to commands, whose futures have been dropped, are ignored.
+    pub fn receive_response(&self, response: CommandResponse) {
+        let waker = {
+            let mut state = self.lock_state();
+
+            state.pending_commands.get_mut(&response.command_id).and_then(|pending_command| {
+                pending_command.result = Some(response.result);
+                pending_command.waker.take()
+            })
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        self.poll_tasks();
+    }
+
+    /// Runs the future to completion, as responses to the commands it awaits
+    /// are received.
+    pub fn spawn(&self, future: impl Future<Output=()> + Send + 'static) {
+        self.lock_tasks().push(Box::pin(future));
+        self.poll_tasks();
+    }
+
+    /// Polls all spawned tasks, which have not completed yet. Called
+    /// automatically whenever a task is spawned or a response is received.
+    pub fn poll_tasks(&self) {
+        // Tasks are taken out, so that they can spawn further tasks.
+        let tasks = std::mem::take(&mut *self.lock_tasks());
+        // SAFETY: The vtable functions ignore the data pointer and do nothing.
+        let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+        let mut context = Context::from_waker(&waker);
+        let pending_tasks: Vec<_> = tasks.into_iter()
+            .filter_map(|mut task| match task.as_mut().poll(&mut context) {
+                Poll::Ready(()) => None,
+                Poll::Pending => Some(task),
+            })
+            .collect();
+
+        self.lock_tasks().extend(pending_tasks);
+    }
+}
+
+/// Resolves to the result of a command sent by a `CommandClient`. Polling it
+/// again after it has completed yields `Poll::Pending`.
+pub struct CommandFuture {
+    command_id: usize,
+    state: Arc<Mutex<SharedState>>,
+}
+
+impl CommandFuture {
+    pub fn command_id(&self) -> usize {
+        self.command_id
+    }
+}
+
+impl Future for CommandFuture {
+    type Output = Result<CommandResponseKind, CommandError>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.state.lock()
+            .unwrap_or_else(|e| panic!("State of the command client became poisoned: {}", e));
+        // The command is no longer pending, once the future has completed.
+        let pending_command = match state.pending_commands.get_mut(&self.command_id) {
+            Some(pending_command) => pending_command,
+            None => return Poll::Pending,
+        };
+
+        match pending_command.result.take() {
+            Some(result) => {
+                state.pending_commands.remove(&self.command_id);
+                Poll::Ready(result)
+            },
+            None => {
+                pending_command.waker = Some(context.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl Drop for CommandFuture {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.pending_commands.remove(&self.command_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity_created(command_id: usize, entity: Entity) -> CommandResponse {
+        CommandResponse {
+            command_id,
+            result: Ok(CommandResponseKind::EntityCreate { entity }),
+        }
+    }
+
+    /// Spawns a task awaiting the future and recording its result.
+    fn record<T: Send + 'static>(
+        client: &CommandClient,
+        future: impl Future<Output=T> + Send + 'static,
+    ) -> Arc<Mutex<Option<T>>> {
+        let result = Arc::new(Mutex::new(None));
+        let task_result = result.clone();
+
+        client.spawn(async move {
+            *task_result.lock().unwrap() = Some(future.await);
+        });
+
+        result
+    }
+
+    #[test]
+    fn allocates_command_ids_in_order() {
+        let client = CommandClient::new();
+        let futures: Vec<_> = (0..3).map(|_| client.send(CommandKind::EntityCreate)).collect();
+        let command_ids: Vec<_> = futures.iter().map(CommandFuture::command_id).collect();
+
+        assert_eq!(command_ids, vec![0, 1, 2]);
+        assert_eq!(client.next_command().map(|command| command.id), Some(0));
+        assert_eq!(client.take_commands().iter().map(|command| command.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(client.next_command().is_none());
+    }
+
+    #[test]
+    fn resolves_responses_out_of_order() {
+        let client = CommandClient::new();
+        let first = record(&client, client.send(CommandKind::EntityCreate));
+        let second = record(&client, client.send(CommandKind::EntityCreate));
+
+        client.receive_response(entity_created(1, Entity(2)));
+
+        assert!(first.lock().unwrap().is_none());
+        assert!(matches!(*second.lock().unwrap(), Some(Ok(CommandResponseKind::EntityCreate { entity: Entity(2) }))));
+
+        client.receive_response(entity_created(0, Entity(1)));
+
+        assert!(matches!(*first.lock().unwrap(), Some(Ok(CommandResponseKind::EntityCreate { entity: Entity(1) }))));
+        assert!(client.lock_state().pending_commands.is_empty());
+    }
+
+    #[test]
+    fn completed_futures_remain_pending() {
+        let client = CommandClient::new();
+        let mut future = client.send(CommandKind::EntityCreate);
+        // SAFETY: The vtable functions ignore the data pointer and do nothing.
+        let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+        let mut context = Context::from_waker(&waker);
+
+        assert!(Pin::new(&mut future).poll(&mut context).is_pending());
+
+        client.receive_response(entity_created(0, Entity(1)));
+
+        assert!(matches!(
+            Pin::new(&mut future).poll(&mut context),
+            Poll::Ready(Ok(CommandResponseKind::EntityCreate { entity: Entity(1) })),
+        ));
+        assert!(Pin::new(&mut future).poll(&mut context).is_pending());
+    }
+
+    #[test]
+    fn ignores_responses_to_dropped_futures() {
+        let client = CommandClient::new();
+        let future = client.send(CommandKind::EntityCreate);
+
+        drop(future);
+
+        assert!(client.lock_state().pending_commands.is_empty());
+
+        client.receive_response(entity_created(0, Entity(1)));
+
+        assert!(client.lock_state().pending_commands.is_empty());
+    }
+
+    #[test]
+    fn reports_unexpected_responses() {
+        let client = CommandClient::new();
+        let result = record(&client, client.entity_create());
+
+        client.receive_response(CommandResponse {
+            command_id: 0,
+            result: Ok(CommandResponseKind::EntityRootGet { root_entity: Entity(0) }),
+        });
+
+        assert_eq!(result.lock().unwrap().take().unwrap().err(), Some(RequestError::UnexpectedResponse(UnexpectedResponse {
+            expected: "EntityCreate",
+            received: "EntityRootGet",
+        })));
+
+        let result = record(&client, client.entity_create());
+
+        client.receive_response(CommandResponse {
+            command_id: 1,
+            result: Err(CommandError::PermissionDenied),
+        });
+
+        assert_eq!(result.lock().unwrap().take().unwrap().err(), Some(RequestError::Command(CommandError::PermissionDenied)));
+    }
+
+    #[test]
+    fn spawned_tasks_can_spawn_tasks() {
+        let client = CommandClient::new();
+        let task_client = client.clone();
+        let result = Arc::new(Mutex::new(None));
+        let task_result = result.clone();
+
+        client.spawn(async move {
+            let parent = task_client.entity_create().await.unwrap().entity;
+            let inner_client = task_client.clone();
+
+            task_client.spawn(async move {
+                let child = inner_client.entity_create().await.unwrap().entity;
+
+                *task_result.lock().unwrap() = Some((parent, child));
+            });
+        });
+
+        assert_eq!(client.next_command().map(|command| command.id), Some(0));
+        client.receive_response(entity_created(0, Entity(1)));
+        assert_eq!(client.next_command().map(|command| command.id), Some(1));
+        assert!(result.lock().unwrap().is_none());
+        client.receive_response(entity_created(1, Entity(2)));
+        assert_eq!(*result.lock().unwrap(), Some((Entity(1), Entity(2))));
+        assert!(client.lock_tasks().is_empty());
+    }
+}
@@ -1,4 +1,6 @@
+use ammolite_math::Mat4;
 use serde::{Serialize, Deserialize};
+use crate::{XrActionState, XrEyeGaze, XrHand, XrHandJoints};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Device(pub usize);
@@ -516,6 +518,83 @@ pub enum DeviceEvent {
     },
 }
 
+/// A gamepad button, named after its position on the gamepad, as in gilrs.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum GamepadButton {
+    // Action pad
+    /// The bottom button, such as A on Xbox pads.
+    South,
+    /// The right button, such as B on Xbox pads.
+    East,
+    /// The top button, such as Y on Xbox pads.
+    North,
+    /// The left button, such as X on Xbox pads.
+    West,
+    C,
+    Z,
+    // Triggers
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    // Menu pad
+    Select,
+    Start,
+    Mode,
+    // Sticks
+    LeftThumb,
+    RightThumb,
+    // D-pad
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    Unknown,
+}
+
+/// A gamepad axis, as in gilrs.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    LeftZ,
+    RightStickX,
+    RightStickY,
+    RightZ,
+    DPadX,
+    DPadY,
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GamepadEvent {
+    Connected {
+        name: String,
+    },
+    Disconnected,
+    ButtonPressed {
+        button: GamepadButton,
+    },
+    /// The button is being held down and the platform repeats the press.
+    ButtonRepeated {
+        button: GamepadButton,
+    },
+    ButtonReleased {
+        button: GamepadButton,
+    },
+    /// The value of an analog button, such as a trigger, changed. The value is
+    /// in the range `[0.0, 1.0]`.
+    ButtonChanged {
+        button: GamepadButton,
+        value: f32,
+    },
+    /// The value is in the range `[-1.0, 1.0]`.
+    AxisChanged {
+        axis: GamepadAxis,
+        value: f32,
+    },
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[repr(u32)]
 pub enum XrSessionState {
@@ -530,6 +609,39 @@ pub enum XrSessionState {
     Exiting,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum XrReferenceSpaceType {
+    View,
+    Local,
+    Stage,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum XrViewConfigurationType {
+    PrimaryMono,
+    PrimaryStereo,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum XrPerfSettingsDomain {
+    Cpu,
+    Gpu,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum XrPerfSettingsSubDomain {
+    Compositing,
+    Rendering,
+    Thermal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum XrPerfSettingsNotificationLevel {
+    Normal,
+    Warning,
+    Impaired,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum XrEvent {
     EventsLost {
@@ -542,11 +654,33 @@ pub enum XrEvent {
         state: XrSessionState,
         time_nanos: i64,
     },
-    // TODO:
-    // ReferenceSpaceChangePending(ReferenceSpaceChangePending<'a>),
-    // PerfSettingsEXT(PerfSettingsEXT<'a>),
-    // VisibilityMaskChangedKHR(VisibilityMaskChangedKHR<'a>),
-    // InteractionProfileChanged(InteractionProfileChanged<'a>),
+    /// The origin of the reference space is about to change, such as after the
+    /// user recentered their view.
+    ReferenceSpaceChangePending {
+        reference_space_type: XrReferenceSpaceType,
+        change_time_nanos: i64,
+        /// The pose of the changed space in the previous space, if known.
+        pose_in_previous_space: Option<Mat4>,
+    },
+    PerfSettingsEXT {
+        domain: XrPerfSettingsDomain,
+        sub_domain: XrPerfSettingsSubDomain,
+        from_level: XrPerfSettingsNotificationLevel,
+        to_level: XrPerfSettingsNotificationLevel,
+    },
+    VisibilityMaskChangedKHR {
+        view_configuration_type: XrViewConfigurationType,
+        view_index: u32,
+    },
+    /// The interaction profile bound to a top level user path, such as
+    /// `/user/hand/left`, changed.
+    InteractionProfileChanged {
+        top_level_user_path: String,
+        /// The path of the new interaction profile, such as
+        /// `/interaction_profiles/khr/simple_controller`, or `None`, if no
+        /// profile is bound anymore.
+        interaction_profile: Option<String>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -556,5 +690,35 @@ pub enum Event {
         device_id: Device,
         event: DeviceEvent,
     },
+    Gamepad {
+        device_id: Device,
+        event: GamepadEvent,
+    },
     Xr(XrEvent),
+    /// The state of an action declared via `XrActionSetsDeclare` changed.
+    XrAction(XrActionState),
+    /// The joints of a hand moved, or the hand started or stopped being
+    /// tracked.
+    XrHandTracking {
+        hand: XrHand,
+        joints: Option<XrHandJoints>,
+    },
+    /// The gaze moved, or was lost if `None`. Only delivered after opting in
+    /// via `XrEyeGazeEnabledSet`.
+    XrEyeGaze(Option<XrEyeGaze>),
+}
+
+/// An event, as delivered to the Mapp, along with when it occurred.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    pub event: Event,
+    /// The time the host received the event at, in nanoseconds. Uses the same
+    /// clock as the times reported by XR events, such as `XrEyeGaze::time_nanos`,
+    /// that is the `XrTime` of the OpenXR runtime, so both compare directly.
+    /// Hosts without an OpenXR session use any monotonic clock, whose origin
+    /// stays fixed while the Mapp is running.
+    pub timestamp_nanos: i64,
+    /// The number of times `update` had been called on the Mapp, when the
+    /// event was delivered.
+    pub frame: u64,
 }
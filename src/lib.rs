@@ -1,9 +1,25 @@
 use ammolite_math::{Mat4, Vec3};
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, Visitor};
+use paste::paste;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
 
+pub mod buffer;
+pub mod client;
 pub mod event;
+pub mod native;
+pub mod protocol;
+pub mod xr;
 
+pub use buffer::*;
+pub use client::*;
 pub use event::*;
+pub use native::*;
+pub use protocol::*;
+pub use xr::*;
 pub use proc_macro_mapp::mapp;
 
 pub mod mlib {
@@ -19,21 +35,64 @@ pub struct IO {
     pub err: Vec<u8>,
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
-pub struct Base64ByteSlice(String);
+/// A byte buffer, serialized as a base64 string by human-readable formats and as
+/// raw bytes by binary formats.
+#[derive(Default, Debug, Clone)]
+pub struct Base64ByteSlice(Vec<u8>);
 
 impl Base64ByteSlice {
     pub fn into_bytes(self) -> Vec<u8> {
-        base64::decode(&self.0)
-            .expect("Invalid base64 byte slice. Was there an error during encoding?")
+        self.0
     }
 }
 
 impl<T: AsRef<[u8]>> From<T> for Base64ByteSlice {
     fn from(other: T) -> Self {
-        let bytes = other.as_ref();
-        let string = base64::encode(bytes);
-        Self(string)
+        Self(other.as_ref().to_vec())
+    }
+}
+
+impl Serialize for Base64ByteSlice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&base64::encode(&self.0))
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Base64ByteSlice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Base64ByteSliceVisitor;
+
+        impl<'de> Visitor<'de> for Base64ByteSliceVisitor {
+            type Value = Base64ByteSlice;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a base64 string or a byte array")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                base64::decode(value)
+                    .map(Base64ByteSlice)
+                    .map_err(|e| E::custom(format!("Invalid base64 byte slice: {}", e)))
+            }
+
+            fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+                Ok(Base64ByteSlice(value.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(Base64ByteSlice(value))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(Base64ByteSliceVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(Base64ByteSliceVisitor)
+        }
     }
 }
 
@@ -41,6 +100,15 @@ impl<T: AsRef<[u8]>> From<T> for Base64ByteSlice {
 pub struct Model(pub usize);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Entity(pub usize);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ModelUpload(pub usize);
+
+/// The encoding of the data of a model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ModelFormat {
+    /// A glTF 2.0 asset, either binary (`.glb`) or JSON with embedded buffers.
+    Gltf,
+}
 
 #[derive(PartialEq, Clone, Default, Debug, Serialize, Deserialize)]
 pub struct ViewFov {
@@ -56,11 +124,110 @@ pub struct View {
     pub fov: ViewFov,
 }
 
+/// The coordinate space a transform is expressed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Space {
+    /// Relative to the parent entity.
+    Local,
+    /// Relative to the root entity.
+    World,
+}
+
+/// The device to play haptic feedback on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HapticTarget {
+    /// A device reporting `Event::Device` or `Event::Gamepad`.
+    Device(Device),
+    /// The XR controller of a top level user path, such as `/user/hand/left`.
+    XrSubactionPath(String),
+}
+
+/// An axis-aligned bounding box.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+/// A set of up to 32 layers an entity belongs to, or a ray traces against.
+/// Entities belong to all layers, unless set otherwise via `EntityLayerSet`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LayerMask(pub u32);
+
+impl LayerMask {
+    pub const NONE: LayerMask = LayerMask(0);
+    pub const ALL: LayerMask = LayerMask(u32::MAX);
+
+    /// Returns `true`, if the masks share at least one layer.
+    pub fn intersects(self, other: LayerMask) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl Default for LayerMask {
+    fn default() -> Self {
+        LayerMask::ALL
+    }
+}
+
+/// Which intersections a ray trace reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RayTraceMode {
+    /// Only the intersection closest to the origin.
+    Closest,
+    /// Any single intersection, not necessarily the closest one. Cheaper than
+    /// `Closest`, when only the presence of an obstacle matters.
+    Any,
+    /// All intersections.
+    All,
+}
+
+/// A point where a traced ray hit an entity.
+///
+/// The fields describing the surface are `None`, if the host does not provide
+/// them or the hit geometry lacks them, such as texture coordinates of a
+/// primitive without `TEXCOORD_0`. Further fields may be added in the future,
+/// so hosts construct intersections using `Intersection::new`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct Intersection {
     pub position: Vec3,
     pub distance_from_origin: f32,
     pub entity: Entity,
+    /// The normal of the surface at the position, in world space, facing
+    /// against the ray.
+    pub normal: Option<Vec3>,
+    /// The barycentric coordinates of the position within the hit triangle,
+    /// weighing its second and third vertex.
+    pub barycentric_coordinates: Option<[f32; 2]>,
+    /// The `TEXCOORD_0` texture coordinates at the position.
+    pub texture_coordinates: Option<[f32; 2]>,
+    /// The index of the glTF mesh hit.
+    pub mesh: Option<usize>,
+    /// The index of the primitive within the mesh.
+    pub primitive: Option<usize>,
+    /// The index of the triangle within the primitive.
+    pub triangle: Option<usize>,
+    /// The index of the glTF material of the primitive.
+    pub material: Option<usize>,
+}
+
+impl Intersection {
+    /// An intersection without any of the optional surface information.
+    pub fn new(position: Vec3, distance_from_origin: f32, entity: Entity) -> Self {
+        Self {
+            position,
+            distance_from_origin,
+            entity,
+            normal: None,
+            barycentric_coordinates: None,
+            texture_coordinates: None,
+            mesh: None,
+            primitive: None,
+            triangle: None,
+            material: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,19 +236,146 @@ pub struct Command {
     pub kind: CommandKind,
 }
 
+/// A typed command, convertible into a `CommandKind`, whose response is
+/// converted from a `CommandResponseKind` into `Response`.
+pub trait CommandRequest: Into<CommandKind> {
+    type Response: TryFrom<CommandResponseKind, Error=UnexpectedResponse>;
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommandResponse {
     pub command_id: usize,
-    pub kind: CommandResponseKind,
+    pub result: Result<CommandResponseKind, CommandError>,
 }
 
+/// The reason the host failed to execute a command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CommandError {
+    /// The entity does not exist or has been destroyed.
+    UnknownEntity {
+        entity: Entity,
+    },
+    /// The model does not exist or has been destroyed.
+    UnknownModel {
+        model: Model,
+    },
+    /// The model data could not be loaded.
+    InvalidModelData {
+        reason: String,
+    },
+    /// The buffer does not exist or has been released.
+    UnknownBuffer {
+        buffer: Buffer,
+    },
+    /// The upload does not exist, or has already been finished or aborted.
+    UnknownUpload {
+        upload: ModelUpload,
+    },
+    /// The host refuses to accept models of the given size.
+    UploadTooLarge {
+        total_size: usize,
+        max_size: usize,
+    },
+    /// The chunk does not fit within the size declared at the beginning of the
+    /// upload.
+    UploadOutOfBounds {
+        offset: usize,
+        len: usize,
+        total_size: usize,
+    },
+    /// The upload was finished before all of its bytes had been received. The
+    /// upload remains open, so that the missing chunks can still be sent.
+    UploadIncomplete {
+        received_size: usize,
+        total_size: usize,
+    },
+    /// Setting the parent would make the entity its own ancestor.
+    CycleDetected {
+        entity: Entity,
+        parent_entity: Entity,
+    },
+    /// `TransactionBegin` was sent while a transaction was already open.
+    TransactionAlreadyOpen,
+    /// `TransactionCommit` or `TransactionAbort` was sent without an open
+    /// transaction.
+    NoOpenTransaction,
+    /// A command within the transaction failed, so none of the commands within
+    /// the transaction took effect.
+    TransactionFailed {
+        command_id: usize,
+        error: Box<CommandError>,
+    },
+    /// The device is not connected or does not support the command.
+    UnknownDevice {
+        device: Device,
+    },
+    /// The action has not been declared via `XrActionSetsDeclare`.
+    UnknownXrAction {
+        action_set: String,
+        action: String,
+    },
+    /// The subaction path has not been declared for the action via
+    /// `XrActionSetsDeclare`.
+    UnknownXrSubactionPath {
+        action_set: String,
+        action: String,
+        subaction_path: String,
+    },
+    /// `XrActionSetsDeclare` was sent more than once.
+    XrActionSetsAlreadyDeclared,
+    /// The host does not implement the command.
+    UnsupportedCommand,
+    /// The Mapp is not allowed to execute the command.
+    PermissionDenied,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::UnknownEntity { entity } => write!(f, "Unknown entity {:?}", entity),
+            CommandError::UnknownModel { model } => write!(f, "Unknown model {:?}", model),
+            CommandError::InvalidModelData { reason } => write!(f, "Invalid model data: {}", reason),
+            CommandError::UnknownBuffer { buffer } => write!(f, "Unknown buffer {:?}", buffer),
+            CommandError::UnknownUpload { upload } => write!(f, "Unknown upload {:?}", upload),
+            CommandError::UploadTooLarge { total_size, max_size } =>
+                write!(f, "Upload of {} bytes exceeds the maximum of {} bytes", total_size, max_size),
+            CommandError::UploadOutOfBounds { offset, len, total_size } =>
+                write!(f, "Chunk of {} bytes at offset {} exceeds the upload size of {} bytes", len, offset, total_size),
+            CommandError::UploadIncomplete { received_size, total_size } =>
+                write!(f, "Upload finished after receiving only {} of {} bytes", received_size, total_size),
+            CommandError::CycleDetected { entity, parent_entity } =>
+                write!(f, "Setting the parent of {:?} to {:?} would create a cycle", entity, parent_entity),
+            CommandError::TransactionAlreadyOpen => write!(f, "A transaction is already open"),
+            CommandError::NoOpenTransaction => write!(f, "No transaction is open"),
+            CommandError::TransactionFailed { command_id, error } =>
+                write!(f, "Transaction rolled back, because command {} failed: {}", command_id, error),
+            CommandError::UnknownDevice { device } => write!(f, "Unknown device {:?}", device),
+            CommandError::UnknownXrAction { action_set, action } =>
+                write!(f, "Unknown XR action `{}` of action set `{}`", action, action_set),
+            CommandError::UnknownXrSubactionPath { action_set, action, subaction_path } =>
+                write!(f, "Unknown subaction path `{}` of XR action `{}` of action set `{}`", subaction_path, action, action_set),
+            CommandError::XrActionSetsAlreadyDeclared => write!(f, "XR action sets have already been declared"),
+            CommandError::UnsupportedCommand => write!(f, "Unsupported command"),
+            CommandError::PermissionDenied => write!(f, "Permission denied"),
+        }
+    }
+}
+
+impl Error for CommandError {}
+
+/// Generates `CommandKind` and `CommandResponseKind`, as well as a request
+/// struct and a response struct per command, linked by `CommandRequest`.
 macro_rules! command_kinds {
-    {$($name:ident $({ $($request_fields:tt)* })? $(-> { $($response_fields:tt)* })?),*$(,)?} => {
+    {$(
+        $name:ident
+        $({ $($request_field:ident: $request_ty:ty),* $(,)? })?
+        $(-> { $($response_field:ident: $response_ty:ty),* $(,)? })?
+    ),*$(,)?} => {
         #[derive(Debug, Clone, Serialize, Deserialize)]
         pub enum CommandKind {
             $(
                 $name $({
-                    $($request_fields)*
+                    $($request_field: $request_ty),*
                 })?
             ),*
         }
@@ -90,57 +384,329 @@ macro_rules! command_kinds {
         pub enum CommandResponseKind {
             $(
                 $name $({
-                    $($response_fields)*
+                    $($response_field: $response_ty),*
                 })?
             ),*
         }
+
+        impl CommandKind {
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(
+                        CommandKind::$name { .. } => stringify!($name),
+                    )*
+                }
+            }
+        }
+
+        impl CommandResponseKind {
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(
+                        CommandResponseKind::$name { .. } => stringify!($name),
+                    )*
+                }
+            }
+        }
+
+        paste! {
+            $(
+                #[derive(Debug, Clone, Serialize, Deserialize)]
+                pub struct [<$name Request>] {
+                    $($(pub $request_field: $request_ty,)*)?
+                }
+
+                #[derive(Debug, Clone, Serialize, Deserialize)]
+                pub struct [<$name Response>] {
+                    $($(pub $response_field: $response_ty,)*)?
+                }
+
+                impl From<[<$name Request>]> for CommandKind {
+                    fn from(request: [<$name Request>]) -> Self {
+                        let [<$name Request>] { $($($request_field),*)? } = request;
+
+                        CommandKind::$name $({ $($request_field),* })?
+                    }
+                }
+
+                impl TryFrom<CommandResponseKind> for [<$name Response>] {
+                    type Error = UnexpectedResponse;
+
+                    fn try_from(kind: CommandResponseKind) -> Result<Self, Self::Error> {
+                        match kind {
+                            CommandResponseKind::$name $({ $($response_field),* })? => Ok([<$name Response>] {
+                                $($($response_field),*)?
+                            }),
+                            kind => Err(UnexpectedResponse {
+                                expected: stringify!($name),
+                                received: kind.name(),
+                            }),
+                        }
+                    }
+                }
+
+                impl CommandRequest for [<$name Request>] {
+                    type Response = [<$name Response>];
+                }
+            )*
+
+            impl CommandClient {
+                $(
+                    pub fn [<$name:snake>](&self $($(, $request_field: $request_ty)*)?)
+                            -> impl Future<Output=Result<[<$name Response>], RequestError>> {
+                        self.request([<$name Request>] {
+                            $($($request_field),*)?
+                        })
+                    }
+                )*
+            }
+        }
     }
 }
 
 command_kinds! {
     ModelCreate {
-        // Due to the fact that we're currently using JSON for command
-        // serialization, it is actually faster to encode byte slices into
-        // a utf-8 base64 string.
+        // When using the JSON5 wire format, it is actually faster to encode
+        // byte slices into a utf-8 base64 string. The binary wire format
+        // transmits the bytes as they are.
         data: Base64ByteSlice,
     } -> {
         model: Model,
     },
+    // Loads the model from a buffer shared via `Buffer::share`, without copying
+    // it into the command. The host releases the buffer once the command has
+    // been processed or, within a transaction, once the transaction has been
+    // committed.
+    ModelCreateFromBuffer {
+        buffer: Buffer,
+    } -> {
+        model: Model,
+    },
+    // Streams a model to the host in chunks, possibly over many updates. The
+    // host may reject the upload as soon as it begins, if `total_size` exceeds
+    // its limits.
+    ModelUploadBegin {
+        total_size: usize,
+        format: ModelFormat,
+    } -> {
+        upload: ModelUpload,
+    },
+    // Chunks may be sent in any order. `received_size` is the total number of
+    // bytes received so far, which can be used to report progress.
+    ModelUploadChunk {
+        upload: ModelUpload,
+        offset: usize,
+        data: Base64ByteSlice,
+    } -> {
+        received_size: usize,
+    },
+    ModelUploadFinish {
+        upload: ModelUpload,
+    } -> {
+        model: Model,
+    },
+    ModelUploadAbort {
+        upload: ModelUpload,
+    },
+    // Entities still referring to the model have their model unset.
+    ModelDestroy {
+        model: Model,
+    } -> {
+        was_referenced: bool,
+    },
+    // The bounds of all meshes of the default scene of the model, in the
+    // coordinate space of the model. The bounds are `None`, if the default
+    // scene has no meshes.
+    ModelBoundsGet {
+        model: Model,
+    } -> {
+        bounds: Option<Aabb>,
+    },
     EntityRootGet -> {
         root_entity: Entity,
     },
     EntityCreate -> {
         entity: Entity,
     },
+    // Destroys the entity and, if `recursive` is `true`, all of its
+    // descendants. Otherwise, the children of the entity are detached (their
+    // parent is set to `None`). The root entity cannot be destroyed.
+    EntityDestroy {
+        entity: Entity,
+        recursive: bool,
+    } -> {
+        destroyed_entities: Vec<Entity>,
+    },
     EntityParentSet {
         entity: Entity,
         parent_entity: Option<Entity>,
     } -> {
         previous_parent_entity: Option<Entity>,
     },
+    EntityParentGet {
+        entity: Entity,
+    } -> {
+        parent_entity: Option<Entity>,
+    },
+    EntityChildrenGet {
+        entity: Entity,
+    } -> {
+        child_entities: Vec<Entity>,
+    },
     EntityModelSet {
         entity: Entity,
         model: Option<Model>,
     } -> {
         previous_model: Option<Model>,
     },
+    EntityModelGet {
+        entity: Entity,
+    } -> {
+        model: Option<Model>,
+    },
     EntityTransformSet {
         entity: Entity,
         transform: Option<Mat4>,
     } -> {
         previous_transform: Option<Mat4>,
     },
+    // A `None` transform stands for the identity. In world space, the
+    // transform is `None` only if no ancestor of the entity has a transform.
+    EntityTransformGet {
+        entity: Entity,
+        space: Space,
+    } -> {
+        transform: Option<Mat4>,
+    },
+    // The bounds of the model of the entity and, if `include_children` is
+    // `true`, of the models of all of its descendants, transformed into the
+    // given space. The bounds are `None`, if there are no models to bound.
+    EntityBoundsGet {
+        entity: Entity,
+        space: Space,
+        include_children: bool,
+    } -> {
+        bounds: Option<Aabb>,
+    },
     // Consider changing the name
     GetViewOrientation {} -> {
         views_per_medium: Vec<Option<Vec<View>>>,
     },
-    // TODO:
-    // * Consider adding the ability to select which entities to ray trace via a mask.
+    EntityLayerSet {
+        entity: Entity,
+        layers: LayerMask,
+    } -> {
+        previous_layers: LayerMask,
+    },
+    EntityLayerGet {
+        entity: Entity,
+    } -> {
+        layers: LayerMask,
+    },
+    // Traces against all entities. Equivalent to `RayTraceMasked` with
+    // `LayerMask::ALL`, no `max_distance` and `RayTraceMode::Closest`.
     RayTrace {
         origin: Vec3,
         direction: Vec3,
     } -> {
         closest_intersection: Option<Intersection>,
     },
+    // Traces only against entities sharing a layer with `mask`, up to
+    // `max_distance` from the origin, if set. Intersections are sorted by
+    // their distance from the origin; `Closest` and `Any` yield at most one.
+    RayTraceMasked {
+        origin: Vec3,
+        direction: Vec3,
+        mask: LayerMask,
+        max_distance: Option<f32>,
+        mode: RayTraceMode,
+    } -> {
+        intersections: Vec<Intersection>,
+    },
+    // Sweeps a sphere of `radius` along the ray, like `RayTraceMasked`. The
+    // `position` of an intersection is the point of contact, while its
+    // `distance_from_origin` is how far the centre of the sphere travelled.
+    SphereCast {
+        origin: Vec3,
+        direction: Vec3,
+        radius: f32,
+        mask: LayerMask,
+        max_distance: Option<f32>,
+        mode: RayTraceMode,
+    } -> {
+        intersections: Vec<Intersection>,
+    },
+    // Finds the entities overlapping an oriented box, given by its world space
+    // transform and half of its size along each local axis. Overlaps report
+    // the point of the entity closest to the centre of the box as `position`,
+    // and are sorted by their `distance_from_origin` to the centre.
+    BoxOverlap {
+        transform: Mat4,
+        half_extents: Vec3,
+        mask: LayerMask,
+    } -> {
+        intersections: Vec<Intersection>,
+    },
+    // Finds the entities overlapping a sphere, reported as by `BoxOverlap`.
+    SphereOverlap {
+        center: Vec3,
+        radius: f32,
+        mask: LayerMask,
+    } -> {
+        intersections: Vec<Intersection>,
+    },
+    // Declares the action sets of the Mapp and the bindings it suggests for
+    // them. As OpenXR attaches action sets to the session, they can only be
+    // declared once. Changes of the states of the actions are delivered as
+    // `Event::XrAction`.
+    XrActionSetsDeclare {
+        action_sets: Vec<XrActionSet>,
+        suggested_bindings: Vec<XrSuggestedBindings>,
+    },
+    // The subaction path, if any, must be one declared for the action.
+    XrActionStateGet {
+        action_set: String,
+        action: String,
+        subaction_path: Option<String>,
+    } -> {
+        state: XrActionState,
+    },
+    // The joints are `None`, if the hand is not tracked at the moment. Updates
+    // are delivered as `Event::XrHandTracking`.
+    XrHandJointsGet {
+        hand: XrHand,
+    } -> {
+        joints: Option<XrHandJoints>,
+    },
+    // As gaze is privacy-sensitive, `Event::XrEyeGaze` is only delivered after
+    // the Mapp opted in. The host may ask the user for consent and fail with
+    // `CommandError::PermissionDenied`.
+    XrEyeGazeEnabledSet {
+        enabled: bool,
+    } -> {
+        previous_enabled: bool,
+    },
+    // Vibrates the target with an `amplitude` between 0 and 1, replacing any
+    // feedback still playing on it. Without a `frequency` in Hz, the host
+    // picks one suited to the device. A `duration_nanos` of 0 plays the
+    // shortest pulse the device supports.
+    HapticPulse {
+        target: HapticTarget,
+        amplitude: f32,
+        frequency: Option<f32>,
+        duration_nanos: u64,
+    },
+    HapticStop {
+        target: HapticTarget,
+    },
+    // Commands sent between `TransactionBegin` and `TransactionCommit` are
+    // executed and responded to as usual, but their effects are presented
+    // together, within a single frame. If any of them fails, the commit fails
+    // with `CommandError::TransactionFailed` and the effects of all of them
+    // are rolled back, as they are by `TransactionAbort`. Transactions cannot
+    // be nested.
+    TransactionBegin,
+    TransactionCommit,
+    TransactionAbort,
     Exit,
 }
@@ -0,0 +1,1271 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use ammolite_math::Mat4;
+use crate::*;
+
+#[derive(Debug, Clone, Default)]
+struct EntityState {
+    parent: Option<Entity>,
+    children: Vec<Entity>,
+    model: Option<Model>,
+    transform: Option<Mat4>,
+    layers: LayerMask,
+}
+
+#[derive(Debug, Clone)]
+struct UploadState {
+    format: ModelFormat,
+    total_size: usize,
+    /// Received chunks and their offsets, in the order of arrival. Memory is
+    /// only allocated for the bytes received, rather than for `total_size`.
+    /// Shared with snapshots of the scene.
+    chunks: Vec<(usize, Arc<[u8]>)>,
+    /// Sorted, non-overlapping ranges of received bytes.
+    received_ranges: Vec<(usize, usize)>,
+}
+
+impl UploadState {
+    fn received_size(&self) -> usize {
+        self.received_ranges.iter().map(|(start, end)| end - start).sum()
+    }
+
+    fn receive(&mut self, offset: usize, bytes: &[u8]) {
+        let end = offset + bytes.len();
+
+        self.chunks.push((offset, Arc::from(bytes)));
+        self.received_ranges.push((offset, end));
+        self.received_ranges.sort();
+
+        let mut merged_ranges: Vec<(usize, usize)> = Vec::with_capacity(self.received_ranges.len());
+
+        for &(start, end) in &self.received_ranges {
+            match merged_ranges.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged_ranges.push((start, end)),
+            }
+        }
+
+        self.received_ranges = merged_ranges;
+    }
+
+    /// Assembles the received chunks, where later chunks overwrite the bytes
+    /// of earlier ones they overlap with.
+    fn into_data(self) -> Vec<u8> {
+        let mut data = vec![0; self.total_size];
+
+        for (offset, bytes) in self.chunks {
+            data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+
+        data
+    }
+}
+
+/// Combines a parent transform with a child transform, where `None` stands for
+/// the identity.
+fn compose_transforms(parent: Option<Mat4>, child: Option<Mat4>) -> Option<Mat4> {
+    match (parent, child) {
+        (Some(parent), Some(child)) => Some(parent * child),
+        (parent, None) => parent,
+        (None, child) => child,
+    }
+}
+
+/// Performs a rudimentary check of whether the data is a glTF asset.
+fn validate_model_data(format: ModelFormat, data: &[u8]) -> Result<(), CommandError> {
+    match format {
+        ModelFormat::Gltf => {
+            let is_binary = data.starts_with(b"glTF");
+            let is_json = data.iter()
+                .find(|byte| !byte.is_ascii_whitespace())
+                .map(|byte| *byte == b'{')
+                .unwrap_or(false);
+
+            if is_binary || is_json {
+                Ok(())
+            } else {
+                Err(CommandError::InvalidModelData {
+                    reason: "The data is neither a binary nor a JSON glTF asset.".to_string(),
+                })
+            }
+        },
+    }
+}
+
+/// The in-memory scene graph maintained by a `NativeHost`.
+///
+/// Model data is shared between clones, so that snapshots taken by
+/// transactions only copy the state of entities and uploads.
+#[derive(Debug, Clone)]
+pub struct Scene {
+    root_entity: Entity,
+    entities: HashMap<Entity, EntityState>,
+    models: HashMap<Model, Arc<Vec<u8>>>,
+    uploads: HashMap<ModelUpload, UploadState>,
+    next_entity: usize,
+    next_model: usize,
+    next_upload: usize,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        let root_entity = Entity(0);
+        let mut entities = HashMap::new();
+
+        entities.insert(root_entity, EntityState::default());
+
+        Self {
+            root_entity,
+            entities,
+            models: HashMap::new(),
+            uploads: HashMap::new(),
+            next_entity: 1,
+            next_model: 0,
+            next_upload: 0,
+        }
+    }
+}
+
+impl Scene {
+    pub fn root_entity(&self) -> Entity {
+        self.root_entity
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item=Entity> + '_ {
+        self.entities.keys().copied()
+    }
+
+    pub fn contains_entity(&self, entity: Entity) -> bool {
+        self.entities.contains_key(&entity)
+    }
+
+    pub fn models(&self) -> impl Iterator<Item=Model> + '_ {
+        self.models.keys().copied()
+    }
+
+    pub fn model_data(&self, model: Model) -> Option<&[u8]> {
+        self.models.get(&model).map(|data| &data[..])
+    }
+
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.entities.get(&entity).and_then(|state| state.parent)
+    }
+
+    pub fn children(&self, entity: Entity) -> &[Entity] {
+        self.entities.get(&entity).map(|state| &state.children[..]).unwrap_or(&[])
+    }
+
+    pub fn model(&self, entity: Entity) -> Option<Model> {
+        self.entities.get(&entity).and_then(|state| state.model)
+    }
+
+    pub fn layers(&self, entity: Entity) -> LayerMask {
+        self.entities.get(&entity).map(|state| state.layers).unwrap_or(LayerMask::NONE)
+    }
+
+    /// Returns the transform of the entity, where `None` stands for the
+    /// identity.
+    pub fn transform(&self, entity: Entity, space: Space) -> Option<Mat4> {
+        let state = self.entities.get(&entity)?;
+
+        match space {
+            Space::Local => state.transform,
+            Space::World => {
+                let parent_transform = state.parent
+                    .and_then(|parent| self.transform(parent, Space::World));
+
+                compose_transforms(parent_transform, state.transform)
+            },
+        }
+    }
+
+    /// Restores the scene to a previous state, without reusing the handles
+    /// allocated in the meantime.
+    fn roll_back(&mut self, snapshot: Scene) {
+        let Scene { next_entity, next_model, next_upload, .. } = *self;
+
+        *self = Scene {
+            next_entity,
+            next_model,
+            next_upload,
+            ..snapshot
+        };
+    }
+
+    fn entity_state(&self, entity: Entity) -> Result<&EntityState, CommandError> {
+        self.entities.get(&entity).ok_or(CommandError::UnknownEntity { entity })
+    }
+
+    fn entity_state_mut(&mut self, entity: Entity) -> Result<&mut EntityState, CommandError> {
+        self.entities.get_mut(&entity).ok_or(CommandError::UnknownEntity { entity })
+    }
+
+    fn create_model(&mut self, format: ModelFormat, data: Vec<u8>) -> Result<Model, CommandError> {
+        validate_model_data(format, &data)?;
+
+        let model = Model(self.next_model);
+
+        self.next_model += 1;
+        self.models.insert(model, Arc::new(data));
+
+        Ok(model)
+    }
+
+    fn destroy_model(&mut self, model: Model) -> Result<bool, CommandError> {
+        self.models.remove(&model).ok_or(CommandError::UnknownModel { model })?;
+
+        let mut was_referenced = false;
+
+        for state in self.entities.values_mut() {
+            if state.model == Some(model) {
+                state.model = None;
+                was_referenced = true;
+            }
+        }
+
+        Ok(was_referenced)
+    }
+
+    fn create_entity(&mut self) -> Entity {
+        let entity = Entity(self.next_entity);
+
+        self.next_entity += 1;
+        self.entities.insert(entity, EntityState::default());
+
+        entity
+    }
+
+    fn destroy_entity(&mut self, entity: Entity, recursive: bool) -> Result<Vec<Entity>, CommandError> {
+        if entity == self.root_entity {
+            return Err(CommandError::PermissionDenied);
+        }
+
+        self.set_parent(entity, None)?;
+
+        let mut destroyed_entities = vec![entity];
+        let mut index = 0;
+
+        while index < destroyed_entities.len() {
+            let state = self.entities.remove(&destroyed_entities[index])
+                .expect("Destroyed entities should be part of the scene.");
+
+            for child in state.children {
+                if recursive {
+                    destroyed_entities.push(child);
+                } else if let Some(child_state) = self.entities.get_mut(&child) {
+                    child_state.parent = None;
+                }
+            }
+
+            index += 1;
+        }
+
+        Ok(destroyed_entities)
+    }
+
+    fn set_parent(&mut self, entity: Entity, parent_entity: Option<Entity>) -> Result<Option<Entity>, CommandError> {
+        self.entity_state(entity)?;
+
+        if entity == self.root_entity {
+            return match parent_entity {
+                None => Ok(None),
+                Some(_) => Err(CommandError::PermissionDenied),
+            };
+        }
+
+        if let Some(parent_entity) = parent_entity {
+            self.entity_state(parent_entity)?;
+
+            let mut ancestor = Some(parent_entity);
+
+            while let Some(current) = ancestor {
+                if current == entity {
+                    return Err(CommandError::CycleDetected { entity, parent_entity });
+                }
+
+                ancestor = self.parent(current);
+            }
+        }
+
+        let previous_parent_entity = std::mem::replace(&mut self.entity_state_mut(entity)?.parent, parent_entity);
+
+        if let Some(previous_parent_entity) = previous_parent_entity {
+            self.entity_state_mut(previous_parent_entity)?.children.retain(|child| *child != entity);
+        }
+
+        if let Some(parent_entity) = parent_entity {
+            self.entity_state_mut(parent_entity)?.children.push(entity);
+        }
+
+        Ok(previous_parent_entity)
+    }
+
+    fn set_model(&mut self, entity: Entity, model: Option<Model>) -> Result<Option<Model>, CommandError> {
+        if let Some(model) = model {
+            if !self.models.contains_key(&model) {
+                return Err(CommandError::UnknownModel { model });
+            }
+        }
+
+        Ok(std::mem::replace(&mut self.entity_state_mut(entity)?.model, model))
+    }
+
+    fn set_transform(&mut self, entity: Entity, transform: Option<Mat4>) -> Result<Option<Mat4>, CommandError> {
+        Ok(std::mem::replace(&mut self.entity_state_mut(entity)?.transform, transform))
+    }
+
+    fn set_layers(&mut self, entity: Entity, layers: LayerMask) -> Result<LayerMask, CommandError> {
+        Ok(std::mem::replace(&mut self.entity_state_mut(entity)?.layers, layers))
+    }
+
+    fn begin_upload(&mut self, total_size: usize, format: ModelFormat) -> ModelUpload {
+        let upload = ModelUpload(self.next_upload);
+
+        self.next_upload += 1;
+        self.uploads.insert(upload, UploadState {
+            format,
+            total_size,
+            chunks: Vec::new(),
+            received_ranges: Vec::new(),
+        });
+
+        upload
+    }
+
+    fn upload_chunk(&mut self, upload: ModelUpload, offset: usize, data: &[u8]) -> Result<usize, CommandError> {
+        let state = self.uploads.get_mut(&upload).ok_or(CommandError::UnknownUpload { upload })?;
+        let total_size = state.total_size;
+
+        if offset.checked_add(data.len()).map(|end| end > total_size).unwrap_or(true) {
+            return Err(CommandError::UploadOutOfBounds {
+                offset,
+                len: data.len(),
+                total_size,
+            });
+        }
+
+        state.receive(offset, data);
+
+        Ok(state.received_size())
+    }
+
+    fn finish_upload(&mut self, upload: ModelUpload) -> Result<Model, CommandError> {
+        let state = self.uploads.get(&upload).ok_or(CommandError::UnknownUpload { upload })?;
+        let received_size = state.received_size();
+        let total_size = state.total_size;
+
+        // Incomplete uploads are kept, so that the missing chunks can be sent
+        // before finishing the upload again.
+        if received_size < total_size {
+            return Err(CommandError::UploadIncomplete { received_size, total_size });
+        }
+
+        let state = self.uploads.remove(&upload)
+            .expect("Finished uploads should be part of the scene.");
+
+        let format = state.format;
+
+        self.create_model(format, state.into_data())
+    }
+
+    fn abort_upload(&mut self, upload: ModelUpload) -> Result<(), CommandError> {
+        self.uploads.remove(&upload)
+            .map(|_| ())
+            .ok_or(CommandError::UnknownUpload { upload })
+    }
+}
+
+/// Looks up a declared action, failing if the subaction path, if any, has not
+/// been declared for it.
+fn declared_xr_action<'a>(
+    action_sets: &'a [XrActionSet],
+    action_set: &str,
+    action: &str,
+    subaction_path: Option<&str>,
+) -> Result<&'a XrAction, CommandError> {
+    let declared_action = action_sets.iter()
+        .filter(|declared_action_set| declared_action_set.name == action_set)
+        .flat_map(|declared_action_set| &declared_action_set.actions)
+        .find(|declared_action| declared_action.name == action)
+        .ok_or_else(|| CommandError::UnknownXrAction {
+            action_set: action_set.to_string(),
+            action: action.to_string(),
+        })?;
+
+    if let Some(subaction_path) = subaction_path {
+        if !declared_action.subaction_paths.iter().any(|declared_path| declared_path == subaction_path) {
+            return Err(CommandError::UnknownXrSubactionPath {
+                action_set: action_set.to_string(),
+                action: action.to_string(),
+                subaction_path: subaction_path.to_string(),
+            });
+        }
+    }
+
+    Ok(declared_action)
+}
+
+/// The default size above which `ModelUploadBegin` fails.
+const DEFAULT_MAX_UPLOAD_SIZE: usize = 1 << 30;
+
+#[derive(Debug)]
+struct Transaction {
+    /// The scene before the transaction began.
+    snapshot: Scene,
+    /// Whether the Mapp had exited before the transaction began.
+    exited: bool,
+    xr_action_sets: Option<Vec<XrActionSet>>,
+    xr_eye_gaze_enabled: bool,
+    /// Buffers loaded by `ModelCreateFromBuffer`, which are only released once
+    /// the transaction is committed.
+    consumed_buffers: Vec<Buffer>,
+    /// The first command within the transaction to fail.
+    failure: Option<(usize, CommandError)>,
+}
+
+/// Runs a Mapp in the current process, without WASM or a renderer, answering its
+/// commands from an in-memory `Scene`. Intended for testing Mapps with
+/// `cargo test`. The Mapp is constructed by the caller, such as via
+/// `Mapp::new`, and initialized by the host via `MappInterface::init`.
+///
+/// The host does not parse models, so ray traces, shape casts, overlap queries
+/// and bounds queries fail with `CommandError::UnsupportedCommand`, as
+/// advertised by the lack of `Capabilities::ray_tracing`. There are no devices
+/// to play haptic feedback on, so haptic commands targeting a device fail with
+/// `CommandError::UnknownDevice`, while those targeting an XR controller are
+/// ignored.
+pub struct NativeHost<M: MappInterface> {
+    mapp: M,
+    scene: Scene,
+    views_per_medium: Vec<Option<Vec<View>>>,
+    max_upload_size: usize,
+    transaction: Option<Transaction>,
+    xr_action_sets: Option<Vec<XrActionSet>>,
+    xr_action_states: HashMap<(String, String, Option<String>), XrActionState>,
+    xr_hand_joints: HashMap<XrHand, XrHandJoints>,
+    xr_eye_gaze_enabled: bool,
+    frame: u64,
+    time: Duration,
+    exited: bool,
+}
+
+impl HostInfo {
+    /// The `HostInfo` a `NativeHost` initializes Mapps with, unless given
+    /// another one.
+    pub fn native() -> Self {
+        HostInfo {
+            protocol_versions: PROTOCOL_VERSION.into(),
+            capabilities: Capabilities::default(),
+        }
+    }
+}
+
+impl<M: MappInterface> NativeHost<M> {
+    /// Initializes the Mapp with `HostInfo::native()`.
+    pub fn new(mapp: M) -> Self {
+        Self::with_host_info(mapp, HostInfo::native())
+            .expect("The native host info should support the protocol version of this crate.")
+    }
+
+    /// Initializes the Mapp with the given `HostInfo`, such as one with
+    /// different capabilities. Fails with `MappError::ProtocolMismatch`, if
+    /// the host info does not support the protocol version of this crate.
+    pub fn with_host_info(mut mapp: M, host_info: HostInfo) -> Result<Self, MappError> {
+        if !host_info.protocol_versions.supports(PROTOCOL_VERSION) {
+            return Err(MappError::ProtocolMismatch(ProtocolMismatch {
+                mapp_version: PROTOCOL_VERSION,
+                host_versions: host_info.protocol_versions,
+            }));
+        }
+
+        mapp.init(&host_info);
+
+        Ok(Self {
+            mapp,
+            scene: Scene::default(),
+            views_per_medium: Vec::new(),
+            max_upload_size: DEFAULT_MAX_UPLOAD_SIZE,
+            transaction: None,
+            xr_action_sets: None,
+            xr_action_states: HashMap::new(),
+            xr_hand_joints: HashMap::new(),
+            xr_eye_gaze_enabled: false,
+            frame: 0,
+            time: Duration::default(),
+            exited: false,
+        })
+    }
+
+    pub fn mapp(&self) -> &M {
+        &self.mapp
+    }
+
+    pub fn mapp_mut(&mut self) -> &mut M {
+        &mut self.mapp
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    /// Sets the views returned by `GetViewOrientation`.
+    pub fn set_views_per_medium(&mut self, views_per_medium: Vec<Option<Vec<View>>>) {
+        self.views_per_medium = views_per_medium;
+    }
+
+    /// Sets the size above which `ModelUploadBegin` fails. Defaults to 1 GiB.
+    pub fn set_max_upload_size(&mut self, max_upload_size: usize) {
+        self.max_upload_size = max_upload_size;
+    }
+
+    /// Returns the action sets declared by the Mapp via `XrActionSetsDeclare`.
+    pub fn xr_action_sets(&self) -> Option<&[XrActionSet]> {
+        self.xr_action_sets.as_deref()
+    }
+
+    /// Sets the state returned by `XrActionStateGet`, delivers it to the Mapp
+    /// as `Event::XrAction` and processes the commands it sends. Fails, if the
+    /// Mapp has not declared the action or its subaction path, as
+    /// `XrActionStateGet` would.
+    pub fn send_xr_action_state(&mut self, state: XrActionState) -> Result<(), CommandError> {
+        let action_sets = self.xr_action_sets.as_deref().unwrap_or(&[]);
+
+        declared_xr_action(action_sets, &state.action_set, &state.action, state.subaction_path.as_deref())?;
+
+        let key = (state.action_set.clone(), state.action.clone(), state.subaction_path.clone());
+
+        self.xr_action_states.insert(key, state.clone());
+        self.send_event(Event::XrAction(state));
+
+        Ok(())
+    }
+
+    /// Sets the joints returned by `XrHandJointsGet`, delivers them to the Mapp
+    /// as `Event::XrHandTracking` and processes the commands it sends.
+    pub fn send_xr_hand_joints(&mut self, hand: XrHand, joints: Option<XrHandJoints>) {
+        match &joints {
+            Some(joints) => self.xr_hand_joints.insert(hand, joints.clone()),
+            None => self.xr_hand_joints.remove(&hand),
+        };
+
+        self.send_event(Event::XrHandTracking { hand, joints });
+    }
+
+    /// Returns `true`, once the Mapp has sent the `Exit` command.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Updates the Mapp and processes the commands it sends. The host time
+    /// only advances by `elapsed`, so that event timestamps are deterministic.
+    pub fn update(&mut self, elapsed: Duration) {
+        self.time += elapsed;
+        self.mapp.update(elapsed);
+        self.frame += 1;
+        self.process_commands();
+    }
+
+    /// Delivers the event to the Mapp, stamped with the current host time and
+    /// frame, and processes the commands it sends. `Event::XrEyeGaze` is
+    /// dropped, unless the Mapp opted in via `XrEyeGazeEnabledSet`.
+    pub fn send_event(&mut self, event: Event) {
+        if matches!(event, Event::XrEyeGaze(_)) && !self.xr_eye_gaze_enabled {
+            return;
+        }
+
+        self.mapp.receive_event(EventEnvelope {
+            event,
+            timestamp_nanos: self.time.as_nanos() as i64,
+            frame: self.frame,
+        });
+        self.process_commands();
+    }
+
+    pub fn flush_io(&mut self) -> IO {
+        self.mapp.flush_io()
+    }
+
+    /// Executes commands sent by the Mapp, until it has none left.
+    pub fn process_commands(&mut self) {
+        loop {
+            let commands = self.mapp.send_commands();
+
+            if commands.is_empty() {
+                break;
+            }
+
+            let responses = commands.into_iter()
+                .map(|command| {
+                    let is_transaction_command = matches!(
+                        command.kind,
+                        CommandKind::TransactionBegin | CommandKind::TransactionCommit | CommandKind::TransactionAbort
+                    );
+                    let result = self.execute(command.kind);
+
+                    if let (Some(transaction), Err(error)) = (&mut self.transaction, &result) {
+                        if !is_transaction_command && transaction.failure.is_none() {
+                            transaction.failure = Some((command.id, error.clone()));
+                        }
+                    }
+
+                    CommandResponse {
+                        command_id: command.id,
+                        result,
+                    }
+                })
+                .collect();
+
+            self.mapp.receive_command_responses(responses);
+        }
+    }
+
+    /// Undoes the effects of the commands within the transaction.
+    fn roll_back(&mut self, transaction: Transaction) {
+        self.scene.roll_back(transaction.snapshot);
+        self.exited = transaction.exited;
+        self.xr_action_sets = transaction.xr_action_sets;
+        self.xr_eye_gaze_enabled = transaction.xr_eye_gaze_enabled;
+    }
+
+    fn execute(&mut self, kind: CommandKind) -> Result<CommandResponseKind, CommandError> {
+        let scene = &mut self.scene;
+
+        Ok(match kind {
+            CommandKind::ModelCreate { data } => CommandResponseKind::ModelCreate {
+                model: scene.create_model(ModelFormat::Gltf, data.into_bytes())?,
+            },
+            CommandKind::ModelCreateFromBuffer { buffer } => {
+                // The buffer lives in the memory of this process. Within a
+                // transaction, it is kept until the transaction is committed,
+                // so that it can still be used after a rollback.
+                let data = match &mut self.transaction {
+                    Some(transaction) => {
+                        if transaction.consumed_buffers.contains(&buffer) {
+                            return Err(CommandError::UnknownBuffer { buffer });
+                        }
+
+                        let data = buffer.with_bytes(|bytes| bytes.to_vec())
+                            .ok_or(CommandError::UnknownBuffer { buffer })?;
+
+                        transaction.consumed_buffers.push(buffer);
+                        data
+                    },
+                    None => buffer.release().ok_or(CommandError::UnknownBuffer { buffer })?,
+                };
+
+                CommandResponseKind::ModelCreateFromBuffer {
+                    model: scene.create_model(ModelFormat::Gltf, data)?,
+                }
+            },
+            CommandKind::ModelUploadBegin { total_size, format } => {
+                if total_size > self.max_upload_size {
+                    return Err(CommandError::UploadTooLarge {
+                        total_size,
+                        max_size: self.max_upload_size,
+                    });
+                }
+
+                CommandResponseKind::ModelUploadBegin {
+                    upload: scene.begin_upload(total_size, format),
+                }
+            },
+            CommandKind::ModelUploadChunk { upload, offset, data } => CommandResponseKind::ModelUploadChunk {
+                received_size: scene.upload_chunk(upload, offset, &data.into_bytes())?,
+            },
+            CommandKind::ModelUploadFinish { upload } => CommandResponseKind::ModelUploadFinish {
+                model: scene.finish_upload(upload)?,
+            },
+            CommandKind::ModelUploadAbort { upload } => {
+                scene.abort_upload(upload)?;
+                CommandResponseKind::ModelUploadAbort
+            },
+            CommandKind::ModelDestroy { model } => CommandResponseKind::ModelDestroy {
+                was_referenced: scene.destroy_model(model)?,
+            },
+            CommandKind::ModelBoundsGet { model } => {
+                scene.model_data(model).ok_or(CommandError::UnknownModel { model })?;
+                return Err(CommandError::UnsupportedCommand);
+            },
+            CommandKind::EntityRootGet => CommandResponseKind::EntityRootGet {
+                root_entity: scene.root_entity(),
+            },
+            CommandKind::EntityCreate => CommandResponseKind::EntityCreate {
+                entity: scene.create_entity(),
+            },
+            CommandKind::EntityDestroy { entity, recursive } => CommandResponseKind::EntityDestroy {
+                destroyed_entities: scene.destroy_entity(entity, recursive)?,
+            },
+            CommandKind::EntityParentSet { entity, parent_entity } => CommandResponseKind::EntityParentSet {
+                previous_parent_entity: scene.set_parent(entity, parent_entity)?,
+            },
+            CommandKind::EntityParentGet { entity } => CommandResponseKind::EntityParentGet {
+                parent_entity: scene.entity_state(entity)?.parent,
+            },
+            CommandKind::EntityChildrenGet { entity } => CommandResponseKind::EntityChildrenGet {
+                child_entities: scene.entity_state(entity)?.children.clone(),
+            },
+            CommandKind::EntityModelSet { entity, model } => CommandResponseKind::EntityModelSet {
+                previous_model: scene.set_model(entity, model)?,
+            },
+            CommandKind::EntityModelGet { entity } => CommandResponseKind::EntityModelGet {
+                model: scene.entity_state(entity)?.model,
+            },
+            CommandKind::EntityTransformSet { entity, transform } => CommandResponseKind::EntityTransformSet {
+                previous_transform: scene.set_transform(entity, transform)?,
+            },
+            CommandKind::EntityTransformGet { entity, space } => {
+                scene.entity_state(entity)?;
+
+                CommandResponseKind::EntityTransformGet {
+                    transform: scene.transform(entity, space),
+                }
+            },
+            CommandKind::EntityBoundsGet { entity, .. } => {
+                scene.entity_state(entity)?;
+                return Err(CommandError::UnsupportedCommand);
+            },
+            CommandKind::GetViewOrientation {} => CommandResponseKind::GetViewOrientation {
+                views_per_medium: self.views_per_medium.clone(),
+            },
+            CommandKind::EntityLayerSet { entity, layers } => CommandResponseKind::EntityLayerSet {
+                previous_layers: scene.set_layers(entity, layers)?,
+            },
+            CommandKind::EntityLayerGet { entity } => CommandResponseKind::EntityLayerGet {
+                layers: scene.entity_state(entity)?.layers,
+            },
+            CommandKind::RayTrace { .. }
+            | CommandKind::RayTraceMasked { .. }
+            | CommandKind::SphereCast { .. }
+            | CommandKind::BoxOverlap { .. }
+            | CommandKind::SphereOverlap { .. } => return Err(CommandError::UnsupportedCommand),
+            CommandKind::XrActionSetsDeclare { action_sets, suggested_bindings } => {
+                if self.xr_action_sets.is_some() {
+                    return Err(CommandError::XrActionSetsAlreadyDeclared);
+                }
+
+                for binding in suggested_bindings.iter().flat_map(|suggested_bindings| &suggested_bindings.bindings) {
+                    declared_xr_action(&action_sets, &binding.action_set, &binding.action, None)?;
+                }
+
+                self.xr_action_sets = Some(action_sets);
+
+                CommandResponseKind::XrActionSetsDeclare
+            },
+            CommandKind::XrActionStateGet { action_set, action, subaction_path } => {
+                let action_sets = self.xr_action_sets.as_deref().unwrap_or(&[]);
+                let action_type = declared_xr_action(action_sets, &action_set, &action, subaction_path.as_deref())?
+                    .action_type;
+                let key = (action_set, action, subaction_path);
+                let state = self.xr_action_states.get(&key).cloned().unwrap_or_else(|| {
+                    let (action_set, action, subaction_path) = key;
+
+                    XrActionState {
+                        action_set,
+                        action,
+                        subaction_path,
+                        is_active: false,
+                        value: XrActionValue::inactive(action_type),
+                    }
+                });
+
+                CommandResponseKind::XrActionStateGet { state }
+            },
+            CommandKind::XrHandJointsGet { hand } => CommandResponseKind::XrHandJointsGet {
+                joints: self.xr_hand_joints.get(&hand).cloned(),
+            },
+            CommandKind::XrEyeGazeEnabledSet { enabled } => CommandResponseKind::XrEyeGazeEnabledSet {
+                previous_enabled: std::mem::replace(&mut self.xr_eye_gaze_enabled, enabled),
+            },
+            CommandKind::HapticPulse { target: HapticTarget::Device(device), .. }
+            | CommandKind::HapticStop { target: HapticTarget::Device(device) } => {
+                return Err(CommandError::UnknownDevice { device });
+            },
+            CommandKind::HapticPulse { .. } => CommandResponseKind::HapticPulse,
+            CommandKind::HapticStop { .. } => CommandResponseKind::HapticStop,
+            CommandKind::TransactionBegin => {
+                if self.transaction.is_some() {
+                    return Err(CommandError::TransactionAlreadyOpen);
+                }
+
+                self.transaction = Some(Transaction {
+                    snapshot: scene.clone(),
+                    exited: self.exited,
+                    xr_action_sets: self.xr_action_sets.clone(),
+                    xr_eye_gaze_enabled: self.xr_eye_gaze_enabled,
+                    consumed_buffers: Vec::new(),
+                    failure: None,
+                });
+
+                CommandResponseKind::TransactionBegin
+            },
+            CommandKind::TransactionCommit => {
+                let mut transaction = self.transaction.take().ok_or(CommandError::NoOpenTransaction)?;
+
+                if let Some((command_id, error)) = transaction.failure.take() {
+                    self.roll_back(transaction);
+
+                    return Err(CommandError::TransactionFailed {
+                        command_id,
+                        error: Box::new(error),
+                    });
+                }
+
+                for buffer in transaction.consumed_buffers {
+                    buffer.release();
+                }
+
+                CommandResponseKind::TransactionCommit
+            },
+            CommandKind::TransactionAbort => {
+                let transaction = self.transaction.take().ok_or(CommandError::NoOpenTransaction)?;
+
+                self.roll_back(transaction);
+
+                CommandResponseKind::TransactionAbort
+            },
+            CommandKind::Exit => {
+                self.exited = true;
+                CommandResponseKind::Exit
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use super::*;
+
+    /// Sends the queued commands and records the results of their responses,
+    /// as well as the events it receives.
+    #[derive(Default)]
+    struct ScriptedMapp {
+        host_info: Option<HostInfo>,
+        commands: VecDeque<CommandKind>,
+        next_command_id: usize,
+        results: Vec<Result<CommandResponseKind, CommandError>>,
+        events: Vec<EventEnvelope>,
+    }
+
+    impl MappInterface for ScriptedMapp {
+        fn init(&mut self, host_info: &HostInfo) {
+            self.host_info = Some(host_info.clone());
+        }
+
+        fn update(&mut self, _elapsed: Duration) {}
+
+        fn send_command(&mut self) -> Option<Command> {
+            let kind = self.commands.pop_front()?;
+            let id = self.next_command_id;
+
+            self.next_command_id += 1;
+
+            Some(Command { id, kind })
+        }
+
+        fn receive_command_response(&mut self, response: CommandResponse) {
+            self.results.push(response.result);
+        }
+
+        fn flush_io(&mut self) -> IO {
+            IO::default()
+        }
+
+        fn receive_event(&mut self, event: EventEnvelope) {
+            self.events.push(event);
+        }
+    }
+
+    fn execute(host: &mut NativeHost<ScriptedMapp>, commands: Vec<CommandKind>) -> Vec<Result<CommandResponseKind, CommandError>> {
+        host.mapp_mut().commands.extend(commands);
+        host.process_commands();
+
+        std::mem::take(&mut host.mapp_mut().results)
+    }
+
+    #[test]
+    fn new_initializes_mapp() {
+        let host = NativeHost::new(ScriptedMapp::default());
+
+        assert_eq!(host.mapp().host_info, Some(HostInfo::native()));
+
+        let host_info = HostInfo {
+            protocol_versions: ProtocolVersion { major: PROTOCOL_VERSION.major + 1, minor: 0 }.into(),
+            capabilities: Capabilities::default(),
+        };
+
+        match NativeHost::with_host_info(ScriptedMapp::default(), host_info) {
+            Err(MappError::ProtocolMismatch(mismatch)) => assert_eq!(mismatch.mapp_version, PROTOCOL_VERSION),
+            _ => panic!("Expected a protocol mismatch."),
+        }
+    }
+
+    #[test]
+    fn hosts_accept_older_minor_versions() {
+        let host_info = HostInfo {
+            protocol_versions: ProtocolVersion { minor: PROTOCOL_VERSION.minor + 1, ..PROTOCOL_VERSION }.into(),
+            capabilities: Capabilities::default(),
+        };
+
+        assert!(NativeHost::with_host_info(ScriptedMapp::default(), host_info.clone()).is_ok());
+
+        let versions = host_info.protocol_versions;
+        let newer_minor = ProtocolVersion { minor: versions.max.minor + 1, ..versions.max };
+        let older_major = ProtocolVersion { major: versions.max.major - 1, minor: 0 };
+
+        assert!(versions.supports(versions.max));
+        assert!(!versions.supports(newer_minor));
+        assert!(!versions.supports(older_major));
+    }
+
+    #[test]
+    fn set_parent_rejects_cycles() {
+        let mut scene = Scene::default();
+        let root = scene.root_entity();
+        let a = scene.create_entity();
+        let b = scene.create_entity();
+
+        scene.set_parent(a, Some(root)).unwrap();
+        scene.set_parent(b, Some(a)).unwrap();
+
+        assert_eq!(scene.set_parent(a, Some(b)), Err(CommandError::CycleDetected { entity: a, parent_entity: b }));
+        assert_eq!(scene.set_parent(a, Some(a)), Err(CommandError::CycleDetected { entity: a, parent_entity: a }));
+        assert_eq!(scene.set_parent(root, Some(a)), Err(CommandError::PermissionDenied));
+        assert_eq!(scene.parent(a), Some(root));
+        assert_eq!(scene.children(a), &[b]);
+    }
+
+    #[test]
+    fn destroy_entity_recursively() {
+        let mut scene = Scene::default();
+        let root = scene.root_entity();
+        let a = scene.create_entity();
+        let b = scene.create_entity();
+        let c = scene.create_entity();
+
+        scene.set_parent(a, Some(root)).unwrap();
+        scene.set_parent(b, Some(a)).unwrap();
+        scene.set_parent(c, Some(b)).unwrap();
+
+        assert_eq!(scene.destroy_entity(a, true), Ok(vec![a, b, c]));
+        assert_eq!(scene.entities().collect::<Vec<_>>(), vec![root]);
+        assert!(scene.children(root).is_empty());
+        assert_eq!(scene.destroy_entity(root, true), Err(CommandError::PermissionDenied));
+    }
+
+    #[test]
+    fn destroy_entity_detaches_children() {
+        let mut scene = Scene::default();
+        let a = scene.create_entity();
+        let b = scene.create_entity();
+
+        scene.set_parent(b, Some(a)).unwrap();
+
+        assert_eq!(scene.destroy_entity(a, false), Ok(vec![a]));
+        assert!(scene.contains_entity(b));
+        assert_eq!(scene.parent(b), None);
+        assert_eq!(scene.destroy_entity(a, false), Err(CommandError::UnknownEntity { entity: a }));
+    }
+
+    #[test]
+    fn destroy_model_reports_references() {
+        let mut scene = Scene::default();
+        let entity = scene.create_entity();
+        let referenced_model = scene.create_model(ModelFormat::Gltf, b"glTF".to_vec()).unwrap();
+        let unreferenced_model = scene.create_model(ModelFormat::Gltf, b"{}".to_vec()).unwrap();
+
+        scene.set_model(entity, Some(referenced_model)).unwrap();
+
+        assert_eq!(scene.destroy_model(referenced_model), Ok(true));
+        assert_eq!(scene.model(entity), None);
+        assert_eq!(scene.destroy_model(unreferenced_model), Ok(false));
+        assert_eq!(scene.destroy_model(unreferenced_model), Err(CommandError::UnknownModel { model: unreferenced_model }));
+    }
+
+    #[test]
+    fn upload_merges_out_of_order_and_overlapping_chunks() {
+        let mut scene = Scene::default();
+        let upload = scene.begin_upload(6, ModelFormat::Gltf);
+
+        assert_eq!(scene.upload_chunk(upload, 2, b"TF"), Ok(2));
+        assert_eq!(scene.upload_chunk(upload, 0, b"glT"), Ok(4));
+        assert_eq!(scene.upload_chunk(upload, 0, b"gl"), Ok(4));
+        assert_eq!(scene.upload_chunk(upload, 5, b"xx"), Err(CommandError::UploadOutOfBounds {
+            offset: 5,
+            len: 2,
+            total_size: 6,
+        }));
+        assert_eq!(scene.finish_upload(upload), Err(CommandError::UploadIncomplete {
+            received_size: 4,
+            total_size: 6,
+        }));
+        assert_eq!(scene.upload_chunk(upload, 4, b"{}"), Ok(6));
+
+        let model = scene.finish_upload(upload).unwrap();
+
+        assert_eq!(scene.model_data(model), Some(&b"glTF{}"[..]));
+        assert_eq!(scene.finish_upload(upload), Err(CommandError::UnknownUpload { upload }));
+    }
+
+    #[test]
+    fn upload_rejects_sizes_above_limit() {
+        let mut host = NativeHost::new(ScriptedMapp::default());
+        let results = execute(&mut host, vec![
+            CommandKind::ModelUploadBegin { total_size: usize::MAX, format: ModelFormat::Gltf },
+        ]);
+
+        assert_eq!(results[0].as_ref().unwrap_err(), &CommandError::UploadTooLarge {
+            total_size: usize::MAX,
+            max_size: DEFAULT_MAX_UPLOAD_SIZE,
+        });
+    }
+
+    #[test]
+    fn transaction_commit_keeps_effects() {
+        let mut host = NativeHost::new(ScriptedMapp::default());
+        let buffer = Buffer::share(b"glTF".to_vec());
+        let results = execute(&mut host, vec![
+            CommandKind::TransactionBegin,
+            CommandKind::EntityCreate,
+            CommandKind::ModelCreateFromBuffer { buffer },
+            CommandKind::ModelCreateFromBuffer { buffer },
+            CommandKind::TransactionCommit,
+        ]);
+
+        assert_eq!(results[3].as_ref().unwrap_err(), &CommandError::UnknownBuffer { buffer });
+        // The failed command rolls back the whole transaction.
+        assert!(matches!(results[4], Err(CommandError::TransactionFailed { command_id: 3, .. })));
+        assert!(!host.scene().contains_entity(Entity(1)));
+        assert_eq!(host.scene().models().count(), 0);
+        assert!(buffer.location().is_some());
+
+        let results = execute(&mut host, vec![
+            CommandKind::TransactionBegin,
+            CommandKind::EntityCreate,
+            CommandKind::ModelCreateFromBuffer { buffer },
+            CommandKind::TransactionCommit,
+        ]);
+
+        assert!(results.iter().all(Result::is_ok));
+        assert!(host.scene().contains_entity(Entity(2)));
+        assert_eq!(host.scene().models().count(), 1);
+        assert!(buffer.location().is_none());
+    }
+
+    #[test]
+    fn transaction_abort_rolls_back() {
+        let mut host = NativeHost::new(ScriptedMapp::default());
+        let buffer = Buffer::share(b"glTF".to_vec());
+        let results = execute(&mut host, vec![
+            CommandKind::TransactionBegin,
+            CommandKind::EntityCreate,
+            CommandKind::ModelCreateFromBuffer { buffer },
+            CommandKind::Exit,
+            CommandKind::TransactionAbort,
+        ]);
+
+        assert!(results.iter().all(Result::is_ok));
+        assert!(!host.scene().contains_entity(Entity(1)));
+        assert_eq!(host.scene().models().count(), 0);
+        assert!(!host.has_exited());
+        assert_eq!(buffer.release(), Some(b"glTF".to_vec()));
+    }
+
+    #[test]
+    fn transactions_cannot_be_nested() {
+        let mut host = NativeHost::new(ScriptedMapp::default());
+        let results = execute(&mut host, vec![
+            CommandKind::TransactionCommit,
+            CommandKind::TransactionAbort,
+            CommandKind::TransactionBegin,
+            CommandKind::EntityCreate,
+            CommandKind::TransactionBegin,
+            CommandKind::TransactionCommit,
+            CommandKind::TransactionCommit,
+        ]);
+
+        assert_eq!(results[0].as_ref().unwrap_err(), &CommandError::NoOpenTransaction);
+        assert_eq!(results[1].as_ref().unwrap_err(), &CommandError::NoOpenTransaction);
+        assert_eq!(results[4].as_ref().unwrap_err(), &CommandError::TransactionAlreadyOpen);
+        // The failed `TransactionBegin` does not fail the open transaction.
+        assert!(results[5].is_ok());
+        assert_eq!(results[6].as_ref().unwrap_err(), &CommandError::NoOpenTransaction);
+        assert!(host.scene().contains_entity(Entity(1)));
+    }
+
+    #[test]
+    fn transaction_abort_rolls_back_xr_state() {
+        let mut host = NativeHost::new(ScriptedMapp::default());
+        let declare = CommandKind::XrActionSetsDeclare {
+            action_sets: vec![XrActionSet {
+                name: "main".to_string(),
+                localized_name: "Main".to_string(),
+                priority: 0,
+                actions: Vec::new(),
+            }],
+            suggested_bindings: Vec::new(),
+        };
+        let results = execute(&mut host, vec![
+            CommandKind::TransactionBegin,
+            declare.clone(),
+            CommandKind::XrEyeGazeEnabledSet { enabled: true },
+            CommandKind::TransactionAbort,
+            declare.clone(),
+            declare,
+        ]);
+
+        assert!(results[..5].iter().all(Result::is_ok));
+        assert_eq!(results[5].as_ref().unwrap_err(), &CommandError::XrActionSetsAlreadyDeclared);
+        assert_eq!(host.xr_action_sets().map(<[_]>::len), Some(1));
+        assert!(!host.xr_eye_gaze_enabled);
+    }
+
+    #[test]
+    fn eye_gaze_requires_opt_in() {
+        let mut host = NativeHost::new(ScriptedMapp::default());
+
+        host.send_event(Event::XrEyeGaze(None));
+
+        assert!(host.mapp().events.is_empty());
+
+        execute(&mut host, vec![CommandKind::XrEyeGazeEnabledSet { enabled: true }]);
+        host.send_event(Event::XrEyeGaze(None));
+
+        assert!(matches!(host.mapp().events[..], [EventEnvelope { event: Event::XrEyeGaze(None), .. }]));
+
+        let results = execute(&mut host, vec![CommandKind::XrEyeGazeEnabledSet { enabled: false }]);
+
+        assert!(matches!(results[0], Ok(CommandResponseKind::XrEyeGazeEnabledSet { previous_enabled: true })));
+
+        host.send_event(Event::XrEyeGaze(None));
+
+        assert_eq!(host.mapp().events.len(), 1);
+    }
+
+    #[test]
+    fn events_are_stamped_with_frame_and_time() {
+        let mut host = NativeHost::new(ScriptedMapp::default());
+        let events_lost = |lost_event_count| Event::Xr(XrEvent::EventsLost { lost_event_count });
+
+        host.send_event(events_lost(0));
+        host.update(Duration::from_millis(10));
+        host.send_event(events_lost(1));
+        host.send_event(events_lost(2));
+        host.update(Duration::from_millis(5));
+        host.update(Duration::from_millis(5));
+        host.send_event(events_lost(3));
+
+        let stamps: Vec<_> = host.mapp().events.iter()
+            .map(|envelope| match envelope.event {
+                Event::Xr(XrEvent::EventsLost { lost_event_count }) =>
+                    (lost_event_count, envelope.frame, envelope.timestamp_nanos),
+                ref event => panic!("Unexpected event: {:?}", event),
+            })
+            .collect();
+
+        // Events delivered between the `update`s N and N + 1 belong to frame N.
+        assert_eq!(stamps, vec![
+            (0, 0, 0),
+            (1, 1, 10_000_000),
+            (2, 1, 10_000_000),
+            (3, 3, 20_000_000),
+        ]);
+    }
+
+    #[test]
+    fn failed_transaction_commit_rolls_back() {
+        let mut host = NativeHost::new(ScriptedMapp::default());
+        let root = host.scene().root_entity();
+        let unknown_entity = Entity(42);
+        let results = execute(&mut host, vec![
+            CommandKind::TransactionBegin,
+            CommandKind::EntityCreate,
+            CommandKind::EntityParentSet { entity: Entity(1), parent_entity: Some(unknown_entity) },
+            CommandKind::EntityParentSet { entity: Entity(1), parent_entity: Some(root) },
+            CommandKind::TransactionCommit,
+            CommandKind::EntityCreate,
+        ]);
+
+        assert!(matches!(results[1], Ok(CommandResponseKind::EntityCreate { entity: Entity(1) })));
+        assert!(results[3].is_ok());
+        assert_eq!(results[4].as_ref().unwrap_err(), &CommandError::TransactionFailed {
+            command_id: 2,
+            error: Box::new(CommandError::UnknownEntity { entity: unknown_entity }),
+        });
+        // Handles are not reused after a rollback.
+        assert!(matches!(results[5], Ok(CommandResponseKind::EntityCreate { entity: Entity(2) })));
+        assert!(!host.scene().contains_entity(Entity(1)));
+        assert!(host.scene().children(root).is_empty());
+    }
+
+    #[test]
+    fn haptics_fail_for_devices() {
+        let mut host = NativeHost::new(ScriptedMapp::default());
+        let device = Device(3);
+        let results = execute(&mut host, vec![
+            CommandKind::HapticPulse {
+                target: HapticTarget::Device(device),
+                amplitude: 1.0,
+                frequency: None,
+                duration_nanos: 1_000_000,
+            },
+            CommandKind::HapticStop { target: HapticTarget::Device(device) },
+            CommandKind::HapticStop { target: HapticTarget::XrSubactionPath("/user/hand/left".to_string()) },
+        ]);
+
+        assert_eq!(results[0].as_ref().unwrap_err(), &CommandError::UnknownDevice { device });
+        assert_eq!(results[1].as_ref().unwrap_err(), &CommandError::UnknownDevice { device });
+        assert!(matches!(results[2], Ok(CommandResponseKind::HapticStop)));
+    }
+
+    #[test]
+    fn xr_action_state_rejects_undeclared_subaction_paths() {
+        let mut host = NativeHost::new(ScriptedMapp::default());
+        let left_hand = "/user/hand/left".to_string();
+        let right_hand = "/user/hand/right".to_string();
+        let state = |subaction_path: &String| XrActionState {
+            action_set: "main".to_string(),
+            action: "grab".to_string(),
+            subaction_path: Some(subaction_path.clone()),
+            is_active: true,
+            value: XrActionValue::Boolean(true),
+        };
+        let get = |subaction_path: &String| CommandKind::XrActionStateGet {
+            action_set: "main".to_string(),
+            action: "grab".to_string(),
+            subaction_path: Some(subaction_path.clone()),
+        };
+        let unknown_subaction_path = CommandError::UnknownXrSubactionPath {
+            action_set: "main".to_string(),
+            action: "grab".to_string(),
+            subaction_path: right_hand.clone(),
+        };
+
+        execute(&mut host, vec![CommandKind::XrActionSetsDeclare {
+            action_sets: vec![XrActionSet {
+                name: "main".to_string(),
+                localized_name: "Main".to_string(),
+                priority: 0,
+                actions: vec![XrAction {
+                    name: "grab".to_string(),
+                    localized_name: "Grab".to_string(),
+                    action_type: XrActionType::Boolean,
+                    subaction_paths: vec![left_hand.clone()],
+                }],
+            }],
+            suggested_bindings: Vec::new(),
+        }]);
+
+        assert_eq!(host.send_xr_action_state(state(&left_hand)), Ok(()));
+        assert_eq!(host.send_xr_action_state(state(&right_hand)), Err(unknown_subaction_path.clone()));
+
+        let results = execute(&mut host, vec![get(&left_hand), get(&right_hand)]);
+
+        match &results[0] {
+            Ok(CommandResponseKind::XrActionStateGet { state: received_state }) => assert_eq!(received_state, &state(&left_hand)),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert_eq!(results[1].as_ref().unwrap_err(), &unknown_subaction_path);
+    }
+}
@@ -0,0 +1,195 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+/// The version of the protocol between the host and Mapps implemented by this
+/// crate. The major version is incremented on incompatible changes, the minor
+/// version on additions, such as new commands or events. 1.0 is the first
+/// released version, which all changes prior to its release are part of.
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 1, minor: 0 };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// An inclusive range of protocol versions supported by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProtocolVersionRange {
+    pub min: ProtocolVersion,
+    pub max: ProtocolVersion,
+}
+
+impl ProtocolVersionRange {
+    /// Returns `true`, if the version is within the range. Only versions of
+    /// the greatest supported major version are accepted. Newer minor versions
+    /// are rejected as well, as the Mapp may send commands unknown to the
+    /// host, which would fail to deserialize along with the rest of their
+    /// batch.
+    pub fn supports(&self, version: ProtocolVersion) -> bool {
+        self.min <= version && version.major == self.max.major && version.minor <= self.max.minor
+    }
+}
+
+impl From<ProtocolVersion> for ProtocolVersionRange {
+    /// The range of all versions compatible with the given one, that is all
+    /// minor versions up to it, as older minor versions are a subset of the
+    /// protocol.
+    fn from(version: ProtocolVersion) -> Self {
+        Self {
+            min: ProtocolVersion {
+                major: version.major,
+                minor: 0,
+            },
+            max: version,
+        }
+    }
+}
+
+/// Optional features provided by the host.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Capabilities {
+    /// An XR headset is available.
+    pub xr: bool,
+    /// `RayTrace` commands are supported.
+    pub ray_tracing: bool,
+    pub audio: bool,
+}
+
+/// Passed to the Mapp by the host during initialization.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub protocol_versions: ProtocolVersionRange,
+    pub capabilities: Capabilities,
+}
+
+/// The Mapp implements a version of the protocol not supported by the host.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolMismatch {
+    pub mapp_version: ProtocolVersion,
+    pub host_versions: ProtocolVersionRange,
+}
+
+impl fmt::Display for ProtocolMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The Mapp implements protocol version {}, but the host only supports versions {} to {}",
+            self.mapp_version, self.host_versions.min, self.host_versions.max,
+        )
+    }
+}
+
+impl Error for ProtocolMismatch {}
+
+/// An error reported by the Mapp instead of the result of a call made by the
+/// host.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MappCallError {
+    /// The arguments provided by the host could not be deserialized.
+    ArgumentDeserialization {
+        method: String,
+        message: String,
+    },
+    /// The result of the call could not be serialized.
+    ResultSerialization {
+        method: String,
+        message: String,
+    },
+    /// The Mapp panicked during a previous call, leaving its state poisoned.
+    StatePoisoned,
+    /// The method was called before the Mapp had been initialized.
+    Uninitialized {
+        method: String,
+    },
+    /// The Mapp refused to initialize.
+    ProtocolMismatch(ProtocolMismatch),
+}
+
+impl fmt::Display for MappCallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MappCallError::ArgumentDeserialization { method, message } =>
+                write!(f, "Could not deserialize host-provided arguments of the method '{}': {}", method, message),
+            MappCallError::ResultSerialization { method, message } =>
+                write!(f, "Could not serialize the result of calling the method '{}': {}", method, message),
+            MappCallError::StatePoisoned => write!(f, "Global state of the Mapp became poisoned"),
+            MappCallError::Uninitialized { method } =>
+                write!(f, "Method '{}' called without initialization of the Mapp", method),
+            MappCallError::ProtocolMismatch(mismatch) => mismatch.fmt(f),
+        }
+    }
+}
+
+impl Error for MappCallError {}
+
+/// An error which occurred while the host was calling a Mapp.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MappError {
+    /// The host could not serialize the arguments or deserialize the result.
+    Serialization {
+        method: String,
+        message: String,
+    },
+    /// The Mapp trapped during the call.
+    Trap {
+        method: String,
+        message: String,
+    },
+    /// The Mapp implements a protocol version not supported by the host.
+    ProtocolMismatch(ProtocolMismatch),
+    /// The Mapp reported an error instead of a result.
+    Call(MappCallError),
+}
+
+impl MappError {
+    /// Creates a `MappError::Trap` from the payload of a panic, which is how
+    /// traps surface in the bindings to the WASM module.
+    pub fn from_panic(method: &str, payload: Box<dyn Any + Send>) -> Self {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "Unknown trap".to_string()
+        };
+
+        MappError::Trap {
+            method: method.to_string(),
+            message,
+        }
+    }
+}
+
+impl From<MappCallError> for MappError {
+    fn from(error: MappCallError) -> Self {
+        match error {
+            MappCallError::ProtocolMismatch(mismatch) => MappError::ProtocolMismatch(mismatch),
+            error => MappError::Call(error),
+        }
+    }
+}
+
+impl fmt::Display for MappError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MappError::Serialization { method, message } =>
+                write!(f, "Could not (de)serialize the call of the method '{}': {}", method, message),
+            MappError::Trap { method, message } =>
+                write!(f, "The Mapp trapped during the call of the method '{}': {}", method, message),
+            MappError::ProtocolMismatch(mismatch) => mismatch.fmt(f),
+            MappError::Call(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for MappError {}
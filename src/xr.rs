@@ -0,0 +1,169 @@
+use ammolite_math::{Mat4, Vec3};
+use serde::{Serialize, Deserialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum XrActionType {
+    Boolean,
+    Float,
+    Vector2,
+    Pose,
+}
+
+/// An input or output of the Mapp, such as "grab" or "teleport", bound to
+/// physical inputs by the host.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XrAction {
+    pub name: String,
+    pub localized_name: String,
+    pub action_type: XrActionType,
+    /// Top level user paths, such as `/user/hand/left`, whose states should be
+    /// reported separately.
+    pub subaction_paths: Vec<String>,
+}
+
+/// A group of actions, which are active together.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XrActionSet {
+    pub name: String,
+    pub localized_name: String,
+    /// Action sets of higher priority take precedence over those of lower
+    /// priority bound to the same inputs.
+    pub priority: u32,
+    pub actions: Vec<XrAction>,
+}
+
+/// Binds an action to an input path, such as
+/// `/user/hand/right/input/trigger/value`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XrBinding {
+    pub action_set: String,
+    pub action: String,
+    pub path: String,
+}
+
+/// The bindings suggested for an interaction profile, such as
+/// `/interaction_profiles/khr/simple_controller`. The host or the user may
+/// choose different ones.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XrSuggestedBindings {
+    pub interaction_profile: String,
+    pub bindings: Vec<XrBinding>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum XrActionValue {
+    Boolean(bool),
+    Float(f32),
+    Vector2([f32; 2]),
+    /// The pose relative to the root entity, or `None`, if it is not tracked.
+    Pose(Option<Mat4>),
+}
+
+impl XrActionValue {
+    /// The value of an inactive action of the given type.
+    pub fn inactive(action_type: XrActionType) -> Self {
+        match action_type {
+            XrActionType::Boolean => XrActionValue::Boolean(false),
+            XrActionType::Float => XrActionValue::Float(0.0),
+            XrActionType::Vector2 => XrActionValue::Vector2([0.0, 0.0]),
+            XrActionType::Pose => XrActionValue::Pose(None),
+        }
+    }
+}
+
+/// The current state of an action, optionally restricted to one of its
+/// subaction paths.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XrActionState {
+    pub action_set: String,
+    pub action: String,
+    pub subaction_path: Option<String>,
+    /// `false`, if the action is not bound to any input at the moment.
+    pub is_active: bool,
+    pub value: XrActionValue,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum XrHand {
+    Left,
+    Right,
+}
+
+/// A joint of the hand skeleton of `XR_EXT_hand_tracking`, in the order of its
+/// joint locations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[repr(u32)]
+pub enum XrHandJoint {
+    Palm,
+    Wrist,
+    ThumbMetacarpal,
+    ThumbProximal,
+    ThumbDistal,
+    ThumbTip,
+    IndexMetacarpal,
+    IndexProximal,
+    IndexIntermediate,
+    IndexDistal,
+    IndexTip,
+    MiddleMetacarpal,
+    MiddleProximal,
+    MiddleIntermediate,
+    MiddleDistal,
+    MiddleTip,
+    RingMetacarpal,
+    RingProximal,
+    RingIntermediate,
+    RingDistal,
+    RingTip,
+    LittleMetacarpal,
+    LittleProximal,
+    LittleIntermediate,
+    LittleDistal,
+    LittleTip,
+}
+
+impl XrHandJoint {
+    pub const COUNT: usize = 26;
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XrHandJointLocation {
+    /// The pose relative to the root entity. Only the parts flagged as valid
+    /// are meaningful.
+    pub pose: Mat4,
+    /// The distance from the centre of the joint to the surface of the skin,
+    /// in metres.
+    pub radius: f32,
+    pub position_valid: bool,
+    pub orientation_valid: bool,
+    /// `false`, if the position is inferred rather than actively tracked.
+    pub position_tracked: bool,
+    /// `false`, if the orientation is inferred rather than actively tracked.
+    pub orientation_tracked: bool,
+}
+
+/// The locations of all joints of a hand, indexed by `XrHandJoint`. Boxed, so
+/// that events and responses carrying them stay small.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XrHandJoints {
+    pub joints: Box<[XrHandJointLocation; XrHandJoint::COUNT]>,
+}
+
+impl XrHandJoints {
+    pub fn get(&self, joint: XrHandJoint) -> &XrHandJointLocation {
+        &self.joints[joint as usize]
+    }
+}
+
+/// A gaze ray, as reported by `XR_EXT_eye_gaze_interaction`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XrEyeGaze {
+    /// The origin of the ray relative to the root entity.
+    pub origin: Vec3,
+    /// The normalized direction of the ray relative to the root entity.
+    pub direction: Vec3,
+    /// How confident the runtime is in the gaze, between 0 and 1.
+    pub confidence: f32,
+    /// The time the gaze was sampled at.
+    pub time_nanos: i64,
+}